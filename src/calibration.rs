@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config;
+
+const FILE: &str = "calibration";
+
+/// Per-device, per-channel offsets from dBFS (where a full-scale sine is 0dB)
+/// to dB SPL, persisted in the config directory as tab-separated
+/// "device, channel, offset" lines.
+#[derive(Debug, Clone, Default)]
+pub struct Calibration {
+    pub device: String,
+    offsets: HashMap<(String, String), f32>,
+}
+
+impl Calibration {
+    pub fn load(device: &str) -> Calibration {
+        let mut cal = Calibration {
+            device: device.into(),
+            offsets: HashMap::new(),
+        };
+        let text = match config::path(FILE).map(fs::read_to_string) {
+            Some(Ok(t)) => t,
+            _ => return cal,
+        };
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if let [dev, chan, offset] = fields[..] {
+                if let Ok(offset) = offset.parse() {
+                    cal.offsets.insert((dev.into(), chan.into()), offset);
+                }
+            }
+        }
+        cal
+    }
+
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = config::save_path(FILE)?;
+        let mut lines: Vec<String> = self.offsets.iter()
+            .map(|((dev, chan), offset)| format!("{}\t{}\t{}\n", dev, chan, offset))
+            .collect();
+        lines.sort();
+        fs::write(&path, lines.concat())?;
+        Ok(path)
+    }
+
+    pub fn offset(&self, channel: &str) -> Option<f32> {
+        self.offsets.get(&(self.device.clone(), channel.into())).cloned()
+    }
+
+    pub fn set(&mut self, channel: &str, offset: f32) {
+        self.offsets.insert((self.device.clone(), channel.into()), offset);
+    }
+}
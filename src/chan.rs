@@ -0,0 +1,51 @@
+use std::str::FromStr;
+
+pub const INPUTS: usize = 2;
+
+/// A channel as seen by the views: a named row of gains applied to each frame
+/// of input channels. The plain inputs are just the identity rows.
+#[derive(Debug, Clone)]
+pub struct Mix {
+    pub name: String,
+    pub gains: Vec<f32>,
+}
+
+impl Mix {
+    pub fn apply(&self, frame: &[f32]) -> f32 {
+        frame.iter().zip(&self.gains).map(|(s, g)| s * g).sum()
+    }
+}
+
+impl FromStr for Mix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mix, String> {
+        let gains = match s {
+            "left" => vec![1.0, 0.0],
+            "right" => vec![0.0, 1.0],
+            "mid" => vec![0.5, 0.5],
+            "side" => vec![0.5, -0.5],
+            "sum" => vec![1.0, 1.0],
+            "diff" => vec![1.0, -1.0],
+            _ => {
+                let (name, row) = s.split_once('=')
+                    .ok_or_else(|| format!("unknown channel {} (expected left, right, mid, side, sum, diff or name=gain:gain...)", s))?;
+                let gains = row.split(':')
+                    .map(|g| g.parse::<f32>().map_err(|e| format!("bad gain {} in channel {}: {}", g, name, e)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if gains.len() > INPUTS {
+                    return Err(format!("channel {} has {} gains, but there are only {} inputs", name, gains.len(), INPUTS));
+                }
+                return Ok(Mix { name: name.into(), gains });
+            },
+        };
+        Ok(Mix { name: s.into(), gains })
+    }
+}
+
+pub fn defaults() -> Vec<Mix> {
+    vec![
+        "left".parse().unwrap(),
+        "right".parse().unwrap(),
+    ]
+}
@@ -0,0 +1,19 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// The per-user configuration directory, if the platform has one.
+pub fn dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("audec"))
+}
+
+pub fn path(name: &str) -> Option<PathBuf> {
+    dir().map(|d| d.join(name))
+}
+
+/// Where to save the config file `name`, creating the directory if need be.
+pub fn save_path(name: &str) -> io::Result<PathBuf> {
+    let dir = dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(name))
+}
@@ -0,0 +1,124 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rustfft::{Fft, FftPlanner};
+use rustfft::num_complex::Complex;
+
+// Constant-Q transform by spectral kernels (Brown and Puckette): each bin's
+// windowed complex exponential, as long as it needs to be to resolve a
+// semitone at its frequency, is transformed once up front; each frame then
+// costs one large FFT of the input and a sparse product per bin.
+
+/// Width of every filter: a semitone, whatever the bin spacing.
+const Q: f32 = 16.817154;
+/// Spectral kernel entries below this fraction of the kernel's peak are
+/// dropped.
+const SPARSITY: f32 = 1e-3;
+
+pub struct Cqt {
+    pub bins_per_octave: usize,
+    freqs: Vec<f32>,
+    kernels: Vec<Vec<(usize, Complex<f32>)>>,
+    fft: Arc<dyn Fft<f32>>,
+    buf: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Cqt {
+    pub fn new(rate: f32, fmin: f32, fmax: f32, bins_per_octave: usize) -> Cqt {
+        let fmax = fmax.min(rate / 2.0 / (1.0 + 0.5 / Q));
+        let count = ((fmax / fmin).log2() * bins_per_octave as f32).floor() as usize + 1;
+        let freqs: Vec<f32> = (0 .. count)
+            .map(|k| fmin * 2f32.powf(k as f32 / bins_per_octave as f32))
+            .collect();
+        let size = ((Q * rate / fmin).ceil() as usize).next_power_of_two();
+
+        let mut plan = FftPlanner::new();
+        let fft = plan.plan_fft_forward(size);
+        let mut scratch = vec![Complex { re: 0.0, im: 0.0 }; fft.get_inplace_scratch_len()];
+        let kernels = freqs.iter()
+            .map(|&f| {
+                // Hann-windowed, normalized so a sine of amplitude A reads A/2,
+                // and aligned to the end of the buffer (the newest samples)
+                let len = (Q * rate / f).ceil() as usize;
+                let hann = |n: usize| (PI * n as f32 / len as f32).sin().powi(2);
+                let norm: f32 = (0 .. len).map(hann).sum();
+                let mut kern = vec![Complex { re: 0.0, im: 0.0 }; size];
+                for n in 0 .. len {
+                    let phase = 2.0 * PI * f * n as f32 / rate;
+                    kern[size - len + n] = Complex::from_polar(hann(n) / norm, phase);
+                }
+                fft.process_with_scratch(&mut kern, &mut scratch);
+                let peak = kern.iter().fold(0f32, |m, c| m.max(c.norm()));
+                // By Parseval, <x, k> = <X, K> / size
+                kern.iter().enumerate()
+                    .filter(|(_, c)| c.norm() > SPARSITY * peak)
+                    .map(|(j, c)| (j, c.conj() / size as f32))
+                    .collect()
+            })
+            .collect();
+
+        Cqt {
+            bins_per_octave,
+            freqs,
+            kernels,
+            fft,
+            buf: vec![Complex { re: 0.0, im: 0.0 }; size],
+            scratch,
+        }
+    }
+
+    /// Number of samples of history the transform looks at.
+    pub fn size(&self) -> usize { self.buf.len() }
+
+    pub fn freqs(&self) -> &[f32] { &self.freqs }
+
+    /// One-sided power in each bin of the most recent `size()` samples (a
+    /// full-scale sine is 1/2, as with `Window::power_scale`).
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        let _g = hprof::enter("Cqt::process");
+        assert_eq!(samples.len(), self.buf.len());
+        for (b, &s) in self.buf.iter_mut().zip(samples) {
+            *b = Complex { re: s, im: 0.0 };
+        }
+        self.fft.process_with_scratch(&mut self.buf, &mut self.scratch);
+        out.clear();
+        out.extend(self.kernels.iter().map(|kern| {
+            let c: Complex<f32> = kern.iter().map(|&(j, k)| self.buf[j] * k).sum();
+            2.0 * c.norm_sqr()
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 8000.0;
+
+    #[test]
+    fn bins_are_geometric_up_to_nyquist() {
+        let cqt = Cqt::new(RATE, 110.0, 10000.0, 12);
+        let freqs = cqt.freqs();
+        assert_eq!(freqs[0], 110.0);
+        assert!((freqs[12] - 220.0).abs() < 1e-3);
+        assert!(*freqs.last().unwrap() < RATE / 2.0);
+        assert!(cqt.size().is_power_of_two());
+    }
+
+    #[test]
+    fn sine_reads_its_power_in_its_bin() {
+        let mut cqt = Cqt::new(RATE, 110.0, 2000.0, 12);
+        let samples: Vec<f32> = (0 .. cqt.size())
+            .map(|n| 0.5 * (2.0 * PI * 440.0 * n as f32 / RATE).sin())
+            .collect();
+        let mut out = Vec::new();
+        cqt.process(&samples, &mut out);
+        assert_eq!(out.len(), cqt.freqs().len());
+        let a4 = cqt.freqs().iter().position(|&f| (f - 440.0).abs() < 0.1).unwrap();
+        assert!((out[a4] - 0.125).abs() < 0.01, "{}", out[a4]);
+        // A semitone away is well down; an octave, far down
+        assert!(out[a4 - 1] < 0.5 * out[a4] && out[a4 + 1] < 0.5 * out[a4]);
+        assert!(out[a4 - 12] < 1e-3 * out[a4] && out[a4 + 12] < 1e-3 * out[a4]);
+    }
+}
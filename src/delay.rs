@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use rustfft::{Fft, FftPlanner};
+use rustfft::num_complex::Complex;
+
+pub const SPEED_OF_SOUND: f32 = 343.0;
+
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub reference: usize,
+    pub measure: usize,
+    /// Normalized cross-correlation, indexed by lag + (len - 1) / 2; positive
+    /// lags are the measurement arriving after the reference.
+    pub correlation: Vec<f32>,
+    /// Peak lag in (fractional) samples
+    pub lag: f32,
+    pub peak: f32,
+}
+
+impl Estimate {
+    pub fn max_lag(&self) -> usize { self.correlation.len() / 2 }
+
+    /// The delay of `b` relative to `a`, if this estimate is between them.
+    pub fn lag_between(&self, a: usize, b: usize) -> Option<f32> {
+        if (a, b) == (self.reference, self.measure) {
+            Some(self.lag)
+        } else if (b, a) == (self.reference, self.measure) {
+            Some(-self.lag)
+        } else {
+            None
+        }
+    }
+}
+
+/// FFT-based (linear, zero-padded) cross-correlation between two channels,
+/// with the cross-spectrum averaged over frames, optionally PHAT-weighted
+/// (generalized cross-correlation with phase transform).
+pub struct Finder {
+    pub phat: bool,
+    pub averages: f32,
+    fwd: Arc<dyn Fft<f32>>,
+    inv: Arc<dyn Fft<f32>>,
+    scratch: Vec<Complex<f32>>,
+    xbuf: Vec<Complex<f32>>,
+    ybuf: Vec<Complex<f32>>,
+    cross: Vec<Complex<f32>>,
+    energy: (f32, f32),
+    pub estimate: Estimate,
+}
+
+impl Finder {
+    pub fn new(size: usize, reference: usize, measure: usize, phat: bool, averages: f32) -> Finder {
+        let mut plan = FftPlanner::new();
+        let fwd = plan.plan_fft_forward(size * 2);
+        let inv = plan.plan_fft_inverse(size * 2);
+        let scratch_len = std::cmp::max(fwd.get_inplace_scratch_len(), inv.get_inplace_scratch_len());
+        let zero = Complex { re: 0.0, im: 0.0 };
+        Finder {
+            phat,
+            averages,
+            fwd,
+            inv,
+            scratch: vec![zero; scratch_len],
+            xbuf: vec![zero; size * 2],
+            ybuf: vec![zero; size * 2],
+            cross: vec![zero; size * 2],
+            energy: (0.0, 0.0),
+            estimate: Estimate {
+                reference,
+                measure,
+                correlation: vec![0.0; size * 2 - 1],
+                lag: 0.0,
+                peak: 0.0,
+            },
+        }
+    }
+
+    pub fn update(&mut self, x: &[f32], y: &[f32]) {
+        let n = self.xbuf.len() / 2;
+        let zero = Complex { re: 0.0, im: 0.0 };
+        for (buf, src) in [(&mut self.xbuf, x), (&mut self.ybuf, y)] {
+            buf.fill(zero);
+            for (pt, &s) in buf.iter_mut().zip(src.iter().take(n)) {
+                pt.re = s;
+            }
+        }
+        let ex: f32 = x.iter().take(n).map(|s| s * s).sum();
+        let ey: f32 = y.iter().take(n).map(|s| s * s).sum();
+        self.fwd.process_with_scratch(&mut self.xbuf, &mut self.scratch);
+        self.fwd.process_with_scratch(&mut self.ybuf, &mut self.scratch);
+
+        let alpha = 1.0 / self.averages.max(1.0);
+        self.energy.0 += alpha * (ex - self.energy.0);
+        self.energy.1 += alpha * (ey - self.energy.1);
+        for ((c, xv), yv) in self.cross.iter_mut().zip(&self.xbuf).zip(&self.ybuf) {
+            let d = xv.conj() * yv - *c;
+            *c += d * alpha;
+        }
+
+        // Reuse xbuf for the correlation itself
+        for (out, &c) in self.xbuf.iter_mut().zip(&self.cross) {
+            *out = if self.phat {
+                let mag = c.norm();
+                if mag > f32::EPSILON { c / mag } else { zero }
+            } else {
+                c
+            };
+        }
+        self.inv.process_with_scratch(&mut self.xbuf, &mut self.scratch);
+
+        let norm = if self.phat {
+            1.0 / (2 * n) as f32
+        } else {
+            let den = (self.energy.0 * self.energy.1).sqrt() * (2 * n) as f32;
+            if den > f32::EPSILON { 1.0 / den } else { 0.0 }
+        };
+        let corr = &mut self.estimate.correlation;
+        for (idx, out) in corr.iter_mut().enumerate() {
+            let lag = idx as isize - (n as isize - 1);
+            let src = if lag < 0 { (2 * n as isize + lag) as usize } else { lag as usize };
+            *out = self.xbuf[src].re * norm;
+        }
+
+        let (peak_idx, &peak) = corr.iter().enumerate()
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+            .unwrap();
+        // Parabolic interpolation around the peak for a sub-sample estimate
+        let frac = if peak_idx > 0 && peak_idx + 1 < corr.len() {
+            let (a, b, c) = (corr[peak_idx - 1].abs(), peak.abs(), corr[peak_idx + 1].abs());
+            let den = a - 2.0 * b + c;
+            if den.abs() > f32::EPSILON { 0.5 * (a - c) / den } else { 0.0 }
+        } else {
+            0.0
+        };
+        self.estimate.lag = peak_idx as f32 + frac - (n as f32 - 1.0);
+        self.estimate.peak = peak;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic white noise in [-1, 1).
+    fn noise(len: usize) -> Vec<f32> {
+        let mut state = 0x2545f491u32;
+        (0 .. len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as f32 / u32::MAX as f32 * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn delayed(x: &[f32], by: usize) -> Vec<f32> {
+        std::iter::repeat_n(0.0, by).chain(x.iter().cloned()).take(x.len()).collect()
+    }
+
+    #[test]
+    fn finds_a_delayed_copy() {
+        let x = noise(1024);
+        for phat in [false, true] {
+            let mut finder = Finder::new(1024, 0, 1, phat, 1.0);
+            finder.update(&x, &delayed(&x, 10));
+            assert!((finder.estimate.lag - 10.0).abs() < 0.1, "phat {}: {}", phat, finder.estimate.lag);
+            assert!(finder.estimate.peak > 0.5);
+            assert_eq!(finder.estimate.lag_between(1, 0), Some(-finder.estimate.lag));
+        }
+    }
+
+    #[test]
+    fn finds_an_early_copy() {
+        let x = noise(1024);
+        let mut finder = Finder::new(1024, 0, 1, false, 1.0);
+        finder.update(&delayed(&x, 25), &x);
+        assert!((finder.estimate.lag + 25.0).abs() < 0.1, "{}", finder.estimate.lag);
+    }
+
+    #[test]
+    fn inverted_copy_peaks_negative() {
+        let x = noise(1024);
+        let y: Vec<f32> = delayed(&x, 3).iter().map(|s| -s).collect();
+        let mut finder = Finder::new(1024, 0, 1, false, 1.0);
+        finder.update(&x, &y);
+        assert!((finder.estimate.lag - 3.0).abs() < 0.1, "{}", finder.estimate.lag);
+        assert!(finder.estimate.peak < -0.5);
+    }
+
+    #[test]
+    fn silence_is_finite() {
+        let mut finder = Finder::new(256, 0, 1, true, 4.0);
+        finder.update(&[0.0; 256], &[0.0; 256]);
+        assert!(finder.estimate.lag.is_finite());
+        assert!(finder.estimate.correlation.iter().all(|c| c.is_finite()));
+    }
+}
@@ -0,0 +1,176 @@
+use rustfft::num_complex::Complex;
+
+use crate::window::Window;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Harmonic {
+    /// 1 for the fundamental
+    pub order: usize,
+    pub freq: f32,
+    /// Power relative to full scale (a full-scale sine is 0dB), in dB
+    pub level: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub harmonics: Vec<Harmonic>,
+    /// Ratios, not percentages or dB
+    pub thd: f32,
+    pub thd_n: f32,
+    /// dB
+    pub sinad: f32,
+    pub snr: f32,
+    /// Bits
+    pub enob: f32,
+}
+
+/// Locate the strongest tone in a spectrum (as computed by `main`: windowed,
+/// scaled by 1/sqrt(N)) and measure its first `count` harmonics. Tone powers
+/// are summed over the window's main lobe and normalized by its coherent gain
+/// and equivalent noise bandwidth, so they read the same for every window.
+pub fn analyze(spectrum: &[Complex<f32>], rate: f32, window: &dyn Window, count: usize) -> Option<Analysis> {
+    let n = spectrum.len();
+    let half = n / 2;
+    let lobe = (2.0 * window.enbw()).ceil() as usize;
+    let norm = window.power_scale();
+    // Normalized so a full-scale sine (power 1/2) reads as 0dB
+    let to_db = |p: f32| 10.0 * (2.0 * p).max(1e-20).log10();
+    let power = |bin: usize| spectrum[bin].norm_sqr() * norm;
+    let band = |center: usize| {
+        let (a, b) = (center.saturating_sub(lobe), std::cmp::min(center + lobe + 1, half));
+        (a .. b).map(power).sum::<f32>()
+    };
+
+    if half <= 2 * lobe {
+        return None;
+    }
+    let (peak, _) = (lobe .. half)
+        .map(|b| (b, power(b)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    // Parabolic interpolation of the log magnitude for a sub-bin frequency
+    let delta = if peak > 0 && peak + 1 < half {
+        let (a, b, c) = (power(peak - 1).max(1e-30).ln(), power(peak).max(1e-30).ln(), power(peak + 1).max(1e-30).ln());
+        let den = a - 2.0 * b + c;
+        if den.abs() > f32::EPSILON { 0.5 * (a - c) / den } else { 0.0 }
+    } else {
+        0.0
+    };
+    let f0_bin = peak as f32 + delta;
+    let fundamental = band(peak);
+    if fundamental <= 0.0 {
+        return None;
+    }
+    // Everything else, summed apart from the fundamental so that it isn't
+    // lost to rounding beside it
+    let lobe_bins = peak.saturating_sub(lobe) ..= peak + lobe;
+    let noise_dist: f32 = (lobe .. half).filter(|b| !lobe_bins.contains(b)).map(power).sum::<f32>().max(1e-20);
+
+    let mut harmonics = vec![Harmonic {
+        order: 1,
+        freq: f0_bin * rate / n as f32,
+        level: to_db(fundamental),
+    }];
+    let mut distortion = 0f32;
+    for order in 2 ..= count {
+        let expected = (f0_bin * order as f32).round() as usize;
+        if expected + lobe >= half {
+            break;
+        }
+        // The harmonic may be a little off the ideal bin
+        let bin = (expected - lobe ..= expected + lobe)
+            .max_by(|&a, &b| power(a).total_cmp(&power(b)))
+            .unwrap();
+        let p = band(bin);
+        distortion += p;
+        harmonics.push(Harmonic {
+            order,
+            freq: bin as f32 * rate / n as f32,
+            level: to_db(p),
+        });
+    }
+
+    let noise = (noise_dist - distortion).max(1e-20);
+    let sinad = 10.0 * ((fundamental + noise_dist) / noise_dist).log10();
+    Some(Analysis {
+        harmonics,
+        thd: (distortion / fundamental).sqrt(),
+        thd_n: (noise_dist / fundamental).sqrt(),
+        sinad,
+        snr: 10.0 * (fundamental / noise).log10(),
+        enob: (sinad - 1.76) / 6.02,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window;
+
+    use rustfft::FftPlanner;
+    use std::f32::consts::PI;
+
+    const RATE: f32 = 48000.0;
+    const SIZE: usize = 4096;
+
+    /// Windowed and scaled as `main` does.
+    fn spectrum(signal: &[f32], window: &dyn Window) -> Vec<Complex<f32>> {
+        let mut buf: Vec<Complex<f32>> = signal.iter().zip(window.shape())
+            .map(|(s, w)| Complex { re: s * w, im: 0.0 })
+            .collect();
+        FftPlanner::new().plan_fft_forward(buf.len()).process(&mut buf);
+        let fac = 1.0 / (signal.len() as f32).sqrt();
+        buf.iter().map(|c| c * fac).collect()
+    }
+
+    /// `amps[k]` of each harmonic k + 1 of `freq`.
+    fn tone(freq: f32, amps: &[f32]) -> Vec<f32> {
+        (0 .. SIZE)
+            .map(|n| {
+                let t = n as f32 / RATE;
+                amps.iter().enumerate().map(|(k, a)| a * (2.0 * PI * freq * (k + 1) as f32 * t).sin()).sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measures_a_known_second_harmonic() {
+        let hann = window::windows()["hann"](SIZE);
+        let signal = tone(1001.0, &[0.5, 0.005]);
+        let an = analyze(&spectrum(&signal, &*hann), RATE, &*hann, 5).unwrap();
+        let fundamental = an.harmonics[0];
+        assert!((fundamental.freq - 1001.0).abs() < 2.0, "{}", fundamental.freq);
+        assert!((fundamental.level + 6.02).abs() < 0.2, "{}", fundamental.level);
+        assert!((an.harmonics[1].level + 46.02).abs() < 0.5, "{}", an.harmonics[1].level);
+        assert!((an.thd - 0.01).abs() < 0.001, "{}", an.thd);
+        assert!(an.thd_n >= an.thd);
+    }
+
+    #[test]
+    fn reads_the_same_through_any_window() {
+        let signal = tone(2500.0, &[0.25, 0.0, 0.025]);
+        for name in ["rect", "hann"] {
+            let win = window::windows()[name](SIZE);
+            let an = analyze(&spectrum(&signal, &*win), RATE, &*win, 5).unwrap();
+            assert!((an.harmonics[0].level + 12.04).abs() < 0.3, "{}: {}", name, an.harmonics[0].level);
+            assert!((an.thd - 0.1).abs() < 0.01, "{}: {}", name, an.thd);
+        }
+    }
+
+    #[test]
+    fn quantized_sine_has_its_bits() {
+        // Coherently sampled (an odd number of cycles), so nothing leaks
+        let rect = window::windows()["rect"](SIZE);
+        let steps = (1 << 9) as f32;
+        let signal: Vec<f32> = tone(97.0 * RATE / SIZE as f32, &[0.999]).iter()
+            .map(|s| (s * steps).round() / steps)
+            .collect();
+        let an = analyze(&spectrum(&signal, &*rect), RATE, &*rect, 5).unwrap();
+        assert!((an.enob - 10.0).abs() < 0.5, "{}", an.enob);
+    }
+
+    #[test]
+    fn silence_has_no_tone() {
+        let hann = window::windows()["hann"](SIZE);
+        assert!(analyze(&spectrum(&[0.0; SIZE], &*hann), RATE, &*hann, 5).is_none());
+    }
+}
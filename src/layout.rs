@@ -0,0 +1,353 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::config;
+use crate::placement::Placement;
+use crate::view::pane::{Pane, Tile};
+
+const FILE: &str = "layout";
+/// Shows between tiles
+const GAP_COLOR: Color = Color::RGB(48, 48, 48);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    /// Top to bottom
+    Rows(Vec<Node>),
+    /// Left to right
+    Cols(Vec<Node>),
+    /// Row by row, this many to a row, in equal cells
+    Grid(usize, Vec<Node>),
+    View(String),
+}
+
+/// One node of a layout: a view, or a split of its area between others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: Kind,
+    /// Share of its parent's rows or columns, relative to its siblings
+    pub weight: f32,
+}
+
+impl Node {
+    /// Parse a layout: one node per line, children indented under their
+    /// parent, each `rows`, `cols`, `grid COLUMNS` or a view name, followed
+    /// by an optional weight. `#` starts a comment.
+    pub fn parse(text: &str) -> Result<Node, String> {
+        let mut lines = Vec::new();
+        for (num, line) in text.lines().enumerate() {
+            let content = line.split('#').next().unwrap();
+            if content.trim().is_empty() {
+                continue;
+            }
+            let indent = content.len() - content.trim_start().len();
+            lines.push((num + 1, indent, content.split_whitespace().collect::<Vec<_>>()));
+        }
+        let mut pos = 0;
+        let root = Node::parse_at(&lines, &mut pos)?;
+        match lines.get(pos) {
+            Some((num, ..)) => Err(format!("line {}: more than one top-level node", num)),
+            None => Ok(root),
+        }
+    }
+
+    fn parse_at(lines: &[(usize, usize, Vec<&str>)], pos: &mut usize) -> Result<Node, String> {
+        let (num, indent, words) = lines.get(*pos).ok_or("empty layout")?;
+        *pos += 1;
+        let mut children = Vec::new();
+        while lines.get(*pos).is_some_and(|(_, i, _)| i > indent) {
+            children.push(Node::parse_at(lines, pos)?);
+        }
+        let number = |word: Option<&&str>, what: &str| -> Result<Option<f32>, String> {
+            word.map(|w| w.parse().map_err(|_| format!("line {}: bad {} {}", num, what, w))).transpose()
+        };
+        let (kind, rest) = match words[0] {
+            "rows" => (Kind::Rows(children), &words[1 ..]),
+            "cols" => (Kind::Cols(children), &words[1 ..]),
+            "grid" => {
+                let columns = number(words.get(1), "column count")?
+                    .ok_or_else(|| format!("line {}: grid needs a column count", num))?;
+                (Kind::Grid(std::cmp::max(columns as usize, 1), children), &words[2 ..])
+            },
+            name if children.is_empty() => (Kind::View(name.into()), &words[1 ..]),
+            name => return Err(format!("line {}: view {} can't have children", num, name)),
+        };
+        if let Kind::Rows(c) | Kind::Cols(c) | Kind::Grid(_, c) = &kind {
+            if c.is_empty() {
+                return Err(format!("line {}: {} has nothing in it", num, words[0]));
+            }
+        }
+        if rest.len() > 1 {
+            return Err(format!("line {}: unexpected {}", num, rest[1]));
+        }
+        Ok(Node { kind, weight: number(rest.first(), "weight")?.unwrap_or(1.0) })
+    }
+
+    /// The layout file given, or else the one in the config directory if
+    /// there is one.
+    pub fn load(path: Option<&Path>) -> Result<Option<Node>, String> {
+        let text = match path {
+            Some(p) => fs::read_to_string(p).map_err(|e| format!("reading {}: {}", p.display(), e))?,
+            None => match config::path(FILE).map(fs::read_to_string) {
+                Some(Ok(t)) => t,
+                _ => return Ok(None),
+            },
+        };
+        Node::parse(&text).map(Some)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        match &self.kind {
+            Kind::Rows(c) | Kind::Cols(c) | Kind::Grid(_, c) => c.iter().any(|n| n.contains(name)),
+            Kind::View(n) => n == name,
+        }
+    }
+
+    /// This layout without the views `present` rejects, and the splits left
+    /// empty by that.
+    pub fn prune(&self, present: &dyn Fn(&str) -> bool) -> Option<Node> {
+        let children = |c: &[Node]| -> Option<Vec<Node>> {
+            let c: Vec<Node> = c.iter().filter_map(|n| n.prune(present)).collect();
+            if c.is_empty() { None } else { Some(c) }
+        };
+        let kind = match &self.kind {
+            Kind::Rows(c) => Kind::Rows(children(c)?),
+            Kind::Cols(c) => Kind::Cols(children(c)?),
+            Kind::Grid(n, c) => Kind::Grid(*n, children(c)?),
+            Kind::View(name) if present(name) => Kind::View(name.clone()),
+            Kind::View(_) => return None,
+        };
+        Some(Node { kind, weight: self.weight })
+    }
+
+    /// The area of each view, dividing up `area`.
+    pub fn arrange(&self, area: Rect, out: &mut Vec<(String, Rect)>) {
+        // Edges at the running total of the weights, so nothing is lost to
+        // rounding
+        let split = |c: &[Node], len: u32| -> Vec<(i32, u32)> {
+            let total: f32 = c.iter().map(|n| n.weight.max(0.0)).sum::<f32>().max(f32::EPSILON);
+            let mut sum = 0.0;
+            c.iter().map(|n| {
+                let start = (sum / total * len as f32).round() as i32;
+                sum += n.weight.max(0.0);
+                let end = (sum / total * len as f32).round() as i32;
+                (start, (end - start) as u32)
+            }).collect()
+        };
+        match &self.kind {
+            Kind::Rows(c) => {
+                for (n, (y, h)) in c.iter().zip(split(c, area.height())) {
+                    n.arrange(Rect::new(area.x(), area.y() + y, area.width(), h), out);
+                }
+            },
+            Kind::Cols(c) => {
+                for (n, (x, w)) in c.iter().zip(split(c, area.width())) {
+                    n.arrange(Rect::new(area.x() + x, area.y(), w, area.height()), out);
+                }
+            },
+            Kind::Grid(columns, c) => {
+                let rows = c.len().div_ceil(*columns);
+                let edge = |i: usize, count: usize, len: u32| (i as u32 * len / count as u32) as i32;
+                for (i, n) in c.iter().enumerate() {
+                    let (col, row) = (i % columns, i / columns);
+                    let (x0, x1) = (edge(col, *columns, area.width()), edge(col + 1, *columns, area.width()));
+                    let (y0, y1) = (edge(row, rows, area.height()), edge(row + 1, rows, area.height()));
+                    n.arrange(Rect::new(area.x() + x0, area.y() + y0, (x1 - x0) as u32, (y1 - y0) as u32), out);
+                }
+            },
+            Kind::View(name) => out.push((name.clone(), area)),
+        }
+    }
+}
+
+/// One window holding several views in tiles, arranged by a layout (or in a
+/// grid, without one). Its keys go to the tile under the mouse, and it is
+/// titled after that tile.
+pub struct Tiles {
+    canvas: Rc<RefCell<Canvas<Window>>>,
+    /// Where the window is when not fullscreen
+    windowed: Placement,
+    layout: Option<Node>,
+    tiles: Vec<(String, Rc<RefCell<Tile>>)>,
+    focus: usize,
+    title: String,
+}
+
+impl Tiles {
+    pub fn new(canvas: Canvas<Window>, windowed: Placement, layout: Option<Node>) -> Tiles {
+        Tiles {
+            canvas: Rc::new(RefCell::new(canvas)),
+            windowed,
+            layout,
+            tiles: Vec::new(),
+            focus: 0,
+            title: String::new(),
+        }
+    }
+
+    /// Whether the view `name` belongs in a tile (every view does without a
+    /// layout).
+    pub fn wants(&self, name: &str) -> bool {
+        self.layout.as_ref().is_none_or(|l| l.contains(name))
+    }
+
+    pub fn pane(&mut self, name: &str) -> Pane {
+        let tile = Rc::new(RefCell::new(Tile {
+            id: u32::MAX - self.tiles.len() as u32,
+            area: Rect::new(0, 0, 1, 1),
+            title: name.into(),
+        }));
+        self.tiles.push((name.into(), tile.clone()));
+        Pane::tile(self.canvas.clone(), tile)
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.borrow().window().id()
+    }
+
+    pub fn placement(&self) -> Placement {
+        Placement::of(self.canvas.borrow().window(), &self.windowed)
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        Placement::toggle_fullscreen(self.canvas.borrow_mut().window_mut(), &mut self.windowed);
+    }
+
+    /// Lay out the tiles for the window's current size and clear it, before
+    /// the views draw.
+    pub fn begin(&mut self) {
+        let mut canvas = self.canvas.borrow_mut();
+        let (width, height) = canvas.output_size().expect("getting size");
+        let layout = match &self.layout {
+            Some(l) => l.prune(&|name| self.tiles.iter().any(|(n, _)| n == name)),
+            None if self.tiles.is_empty() => None,
+            None => {
+                let columns = (self.tiles.len() as f32).sqrt().ceil() as usize;
+                let views = self.tiles.iter()
+                    .map(|(name, _)| Node { kind: Kind::View(name.clone()), weight: 1.0 })
+                    .collect();
+                Some(Node { kind: Kind::Grid(columns, views), weight: 1.0 })
+            },
+        };
+        let mut areas = Vec::new();
+        if let Some(l) = layout {
+            l.arrange(Rect::new(0, 0, width, height), &mut areas);
+        }
+        for (name, area) in areas {
+            // A pixel of gap above and left of all but the outer tiles
+            let (dx, dy) = ((area.x() > 0) as i32, (area.y() > 0) as i32);
+            let area = Rect::new(area.x() + dx, area.y() + dy, area.width() - dx as u32, area.height() - dy as u32);
+            if let Some((_, tile)) = self.tiles.iter().find(|(n, _)| *n == name) {
+                tile.borrow_mut().area = area;
+            }
+        }
+        canvas.set_viewport(None);
+        canvas.set_draw_color(GAP_COLOR);
+        canvas.clear();
+    }
+
+    /// Show the window, once the views have drawn.
+    pub fn present(&mut self) {
+        let mut canvas = self.canvas.borrow_mut();
+        if let Some((_, tile)) = self.tiles.get(self.focus) {
+            let title = &tile.borrow().title;
+            if *title != self.title {
+                canvas.window_mut().set_title(title).expect("setting title");
+                self.title = title.clone();
+            }
+        }
+        canvas.set_viewport(None);
+        canvas.present();
+    }
+
+    /// The id that events for `window_id` should go to: that of the focused
+    /// tile if it's this window.
+    pub fn route(&self, window_id: u32) -> u32 {
+        match self.tiles.get(self.focus) {
+            Some((_, tile)) if window_id == self.window_id() => tile.borrow().id,
+            _ => window_id,
+        }
+    }
+
+    /// Focus the tile under the mouse.
+    pub fn motion(&mut self, window_id: u32, x: i32, y: i32) {
+        if window_id != self.window_id() {
+            return;
+        }
+        if let Some(idx) = self.tiles.iter().position(|(_, t)| t.borrow().area.contains_point((x, y))) {
+            self.focus = idx;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(name: &str, weight: f32) -> Node {
+        Node { kind: Kind::View(name.into()), weight }
+    }
+
+    #[test]
+    fn parses_nested_splits() {
+        let layout = Node::parse("rows  # the main split\n  spec 2\n  cols\n    scope\n    vec\n\n  grid 2 0.5\n    loud\n").unwrap();
+        assert_eq!(layout, Node {
+            kind: Kind::Rows(vec![
+                view("spec", 2.0),
+                Node { kind: Kind::Cols(vec![view("scope", 1.0), view("vec", 1.0)]), weight: 1.0 },
+                Node { kind: Kind::Grid(2, vec![view("loud", 1.0)]), weight: 0.5 },
+            ]),
+            weight: 1.0,
+        });
+        assert!(layout.contains("vec") && !layout.contains("rta"));
+    }
+
+    #[test]
+    fn rejects_malformed_layouts() {
+        for (text, error) in [
+            ("", "empty layout"),
+            ("rows\n", "line 1: rows has nothing in it"),
+            ("spec\nscope\n", "line 2: more than one top-level node"),
+            ("spec\n  scope\n", "line 1: view spec can't have children"),
+            ("grid\n  spec\n", "line 1: grid needs a column count"),
+            ("rows\n  spec heavy\n", "line 2: bad weight heavy"),
+            ("rows\n  spec 1 2\n", "line 2: unexpected 2"),
+        ] {
+            assert_eq!(Node::parse(text), Err(error.into()), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn prunes_absent_views_and_empty_splits() {
+        let layout = Node::parse("rows\n  spec\n  cols\n    rta\n    ir\n").unwrap();
+        assert_eq!(layout.prune(&|name| name == "spec"), Some(Node {
+            kind: Kind::Rows(vec![view("spec", 1.0)]),
+            weight: 1.0,
+        }));
+        assert_eq!(layout.prune(&|_| false), None);
+    }
+
+    #[test]
+    fn arranges_by_weight_without_gaps() {
+        let layout = Node::parse("cols\n  spec 2\n  rows\n    scope\n    vec\n").unwrap();
+        let mut areas = Vec::new();
+        layout.arrange(Rect::new(0, 0, 301, 100), &mut areas);
+        assert_eq!(areas, vec![
+            ("spec".to_string(), Rect::new(0, 0, 201, 100)),
+            ("scope".to_string(), Rect::new(201, 0, 100, 50)),
+            ("vec".to_string(), Rect::new(201, 50, 100, 50)),
+        ]);
+
+        let grid = Node::parse("grid 2\n  a\n  b\n  c\n").unwrap();
+        areas.clear();
+        grid.arrange(Rect::new(0, 0, 100, 100), &mut areas);
+        let rects: Vec<Rect> = areas.into_iter().map(|(_, r)| r).collect();
+        assert_eq!(rects, vec![Rect::new(0, 0, 50, 50), Rect::new(50, 0, 50, 50), Rect::new(0, 50, 50, 50)]);
+    }
+}
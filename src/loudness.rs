@@ -0,0 +1,320 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// ITU-R BS.1770-4 / EBU R128 loudness metering. Everything here operates on
+// the raw sample stream (from the audio callback), not on per-frame snapshots.
+
+const BLOCK_HZ: f64 = 10.0;
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_BLOCKS: usize = 30;
+const ABS_GATE: f64 = -70.0;
+const INTEGRATED_GATE: f64 = -10.0;
+const RANGE_GATE: f64 = -20.0;
+const HIST_MAX: f64 = 10.0;
+const HIST_RES: f64 = 0.1;
+pub const HISTORY: usize = 3000;
+
+pub fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Biquad {
+        Biquad { b, a, z: [0.0; 2] }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+
+    pub fn reset(&mut self) {
+        self.z = [0.0; 2];
+    }
+}
+
+// Both K-weighting stages, with coefficients derived for arbitrary rates
+// (the BS.1770 tables are only given for 48kHz).
+fn k_shelf(rate: f64) -> Biquad {
+    let f0 = 1681.974450955533;
+    let g = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(g / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [
+            2.0 * (k * k - 1.0) / a0,
+            (1.0 - k / q + k * k) / a0,
+        ],
+    )
+}
+
+fn k_highpass(rate: f64) -> Biquad {
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    Biquad::new(
+        [1.0, -2.0, 1.0],
+        [
+            2.0 * (k * k - 1.0) / a0,
+            (1.0 - k / q + k * k) / a0,
+        ],
+    )
+}
+
+// Block powers binned at 0.1 LU, which is all the precision gating needs, and
+// keeps integrated/range computation constant-time over arbitrarily long
+// programs.
+#[derive(Debug, Clone)]
+struct Histogram {
+    counts: Vec<u64>,
+    powers: Vec<f64>,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        let bins = ((HIST_MAX - ABS_GATE) / HIST_RES) as usize + 1;
+        Histogram {
+            counts: vec![0; bins],
+            powers: vec![0.0; bins],
+        }
+    }
+
+    fn bin(&self, lufs: f64) -> usize {
+        if lufs <= ABS_GATE {
+            return 0;
+        }
+        std::cmp::min(((lufs - ABS_GATE) / HIST_RES) as usize, self.counts.len() - 1)
+    }
+
+    fn add(&mut self, pow: f64) {
+        let l = lufs(pow);
+        if l <= ABS_GATE {
+            return;
+        }
+        let bin = self.bin(l);
+        self.counts[bin] += 1;
+        self.powers[bin] += pow;
+    }
+
+    fn mean_above(&self, gate: f64) -> Option<f64> {
+        let from = self.bin(gate);
+        let count: u64 = self.counts[from ..].iter().sum();
+        if count == 0 {
+            return None;
+        }
+        Some(self.powers[from ..].iter().sum::<f64>() / count as f64)
+    }
+
+    fn center(&self, bin: usize) -> f64 {
+        ABS_GATE + (bin as f64 + 0.5) * HIST_RES
+    }
+
+    fn clear(&mut self) {
+        self.counts.fill(0);
+        self.powers.fill(0.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Point {
+    pub momentary: f32,
+    pub short_term: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Meter {
+    filters: Vec<[Biquad; 2]>,
+    block_len: usize,
+    block_pos: usize,
+    block_sum: f64,
+    blocks: VecDeque<f64>,
+    gating: Histogram,
+    short: Histogram,
+    history: VecDeque<Point>,
+}
+
+impl Meter {
+    pub fn new(rate: f64, channels: usize) -> Meter {
+        Meter {
+            filters: vec![[k_shelf(rate), k_highpass(rate)]; channels],
+            block_len: (rate / BLOCK_HZ).round() as usize,
+            block_pos: 0,
+            block_sum: 0.0,
+            blocks: VecDeque::with_capacity(SHORT_BLOCKS),
+            gating: Histogram::new(),
+            short: Histogram::new(),
+            history: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for [shelf, hp] in self.filters.iter_mut() {
+            shelf.reset();
+            hp.reset();
+        }
+        self.block_pos = 0;
+        self.block_sum = 0.0;
+        self.blocks.clear();
+        self.gating.clear();
+        self.short.clear();
+        self.history.clear();
+    }
+
+    /// Feed one frame (one sample per channel). All channels are weighted
+    /// equally, which is correct for the L/R (and mono) case.
+    pub fn push(&mut self, frame: &[f32]) {
+        for (samp, [shelf, hp]) in frame.iter().zip(self.filters.iter_mut()) {
+            let y = hp.process(shelf.process(*samp as f64));
+            self.block_sum += y * y;
+        }
+        self.block_pos += 1;
+        if self.block_pos >= self.block_len {
+            self.end_block();
+        }
+    }
+
+    fn end_block(&mut self) {
+        if self.blocks.len() >= SHORT_BLOCKS {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back(self.block_sum / self.block_len as f64);
+        self.block_pos = 0;
+        self.block_sum = 0.0;
+
+        // Gating blocks are 400ms with 75% overlap, i.e. one per 100ms block
+        if let Some(pow) = self.mean_power(MOMENTARY_BLOCKS) {
+            self.gating.add(pow);
+        }
+        if let Some(pow) = self.mean_power(SHORT_BLOCKS) {
+            self.short.add(pow);
+        }
+
+        if self.history.len() >= HISTORY {
+            self.history.pop_front();
+        }
+        let pt = Point {
+            momentary: self.momentary(),
+            short_term: self.short_term(),
+        };
+        self.history.push_back(pt);
+    }
+
+    fn mean_power(&self, blocks: usize) -> Option<f64> {
+        if self.blocks.len() < blocks {
+            return None;
+        }
+        Some(self.blocks.iter().rev().take(blocks).sum::<f64>() / blocks as f64)
+    }
+
+    pub fn momentary(&self) -> f32 {
+        self.mean_power(MOMENTARY_BLOCKS).map_or(f32::NEG_INFINITY, |p| lufs(p) as f32)
+    }
+
+    pub fn short_term(&self) -> f32 {
+        self.mean_power(SHORT_BLOCKS).map_or(f32::NEG_INFINITY, |p| lufs(p) as f32)
+    }
+
+    pub fn integrated(&self) -> f32 {
+        self.gating.mean_above(ABS_GATE)
+            .and_then(|p| self.gating.mean_above(lufs(p) + INTEGRATED_GATE))
+            .map_or(f32::NEG_INFINITY, |p| lufs(p) as f32)
+    }
+
+    /// The (10th, 95th) percentiles of gated short-term loudness; LRA is their
+    /// difference (EBU Tech 3342).
+    pub fn range(&self) -> Option<(f32, f32)> {
+        let gate = lufs(self.short.mean_above(ABS_GATE)?) + RANGE_GATE;
+        let from = self.short.bin(gate);
+        let counts = &self.short.counts[from ..];
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let percentile = |pct: f64| {
+            let target = (total as f64 * pct) as u64;
+            let mut acc = 0u64;
+            for (idx, &count) in counts.iter().enumerate() {
+                acc += count;
+                if acc > target {
+                    return self.short.center(from + idx);
+                }
+            }
+            self.short.center(self.short.counts.len() - 1)
+        };
+        Some((percentile(0.10) as f32, percentile(0.95) as f32))
+    }
+
+    pub fn history(&self) -> &VecDeque<Point> { &self.history }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 48000.0;
+
+    /// Feed `secs` of a 1kHz sine at `dbfs` to every channel of `meter`.
+    fn sine(meter: &mut Meter, channels: usize, dbfs: f64, secs: f64) {
+        let amp = 10f64.powf(dbfs / 20.0);
+        for n in 0 .. (secs * RATE) as usize {
+            let s = (amp * (2.0 * PI * 1000.0 * n as f64 / RATE).sin()) as f32;
+            meter.push(&vec![s; channels]);
+        }
+    }
+
+    #[test]
+    fn mono_sine_reads_3db_below_its_level() {
+        let mut meter = Meter::new(RATE, 1);
+        sine(&mut meter, 1, -20.0, 5.0);
+        assert!((meter.momentary() + 23.0).abs() < 0.1, "{}", meter.momentary());
+        assert!((meter.short_term() + 23.0).abs() < 0.1, "{}", meter.short_term());
+        assert!((meter.integrated() + 23.0).abs() < 0.1, "{}", meter.integrated());
+    }
+
+    #[test]
+    fn stereo_sine_reads_its_level() {
+        // EBU Tech 3341, case 1
+        let mut meter = Meter::new(RATE, 2);
+        sine(&mut meter, 2, -23.0, 20.0);
+        assert!((meter.integrated() + 23.0).abs() < 0.1, "{}", meter.integrated());
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let mut meter = Meter::new(RATE, 2);
+        sine(&mut meter, 2, -23.0, 10.0);
+        sine(&mut meter, 2, -100.0, 10.0);
+        assert!((meter.integrated() + 23.0).abs() < 0.1, "{}", meter.integrated());
+        meter.reset();
+        assert_eq!(meter.integrated(), f32::NEG_INFINITY);
+        assert!(meter.range().is_none());
+    }
+
+    #[test]
+    fn range_of_two_levels() {
+        // EBU Tech 3342, case 1: 20s at -20dBFS then 20s at -30dBFS
+        let mut meter = Meter::new(RATE, 2);
+        sine(&mut meter, 2, -20.0, 20.0);
+        sine(&mut meter, 2, -30.0, 20.0);
+        let (lo, hi) = meter.range().unwrap();
+        assert!((hi - lo - 10.0).abs() < 1.0, "{} to {}", lo, hi);
+    }
+}
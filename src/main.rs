@@ -5,18 +5,33 @@ extern crate rustfft;
 extern crate sdl2;
 
 mod window;
+mod config;
 mod fifo;
+mod harmonics;
+mod pitch;
+mod peaks;
+mod cqt;
+mod reassign;
+mod chan;
+mod calibration;
+mod placement;
+mod delay;
+mod loudness;
+mod sweep;
+mod weighting;
+mod layout;
 mod view;
 
 use std::{iter, thread};
 use std::time::{Instant, Duration};
 use std::sync::{Arc, Mutex};
-use portaudio::stream::{Parameters, InputSettings, CallbackResult, InputCallbackArgs};
+use portaudio::stream::{Parameters, InputSettings, OutputSettings, CallbackResult, InputCallbackArgs, OutputCallbackArgs};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use rustfft::num_complex::Complex;
 use fifo::Fifo;
 use view::View;
+use view::pane::Pane;
 
 const MIN_SAMPS: usize = 256;
 
@@ -24,12 +39,14 @@ const MIN_SAMPS: usize = 256;
 struct ChannelInfo {
     scope: Fifo<f32>,
     win: Fifo<f32>,
+    cq: Fifo<f32>,
 }
 
 #[derive(Debug, Clone)]
 struct State {
-    left: ChannelInfo,
-    right: ChannelInfo,
+    channels: Vec<ChannelInfo>,
+    /// Frames received so far
+    received: u64,
 }
 
 fn main() {
@@ -72,6 +89,18 @@ fn main() {
     let init_spec_height: u32 = matches.value_of("spec-height").unwrap_or("600").parse().expect("getting spectrogram initial height");
     let init_vec_width: u32 = matches.value_of("vec-width").unwrap_or("400").parse().expect("getting vectorscope initial width");
     let init_vec_height: u32 = matches.value_of("vec-height").unwrap_or("400").parse().expect("getting vectorscope initial height");
+    let init_loud_width: u32 = matches.value_of("loud-width").unwrap_or("600").parse().expect("getting loudness initial width");
+    let init_loud_height: u32 = matches.value_of("loud-height").unwrap_or("300").parse().expect("getting loudness initial height");
+    let init_xfer_width: u32 = matches.value_of("xfer-width").unwrap_or("800").parse().expect("getting transfer initial width");
+    let init_xfer_height: u32 = matches.value_of("xfer-height").unwrap_or("600").parse().expect("getting transfer initial height");
+    let init_delay_width: u32 = matches.value_of("delay-width").unwrap_or("600").parse().expect("getting delay initial width");
+    let init_delay_height: u32 = matches.value_of("delay-height").unwrap_or("200").parse().expect("getting delay initial height");
+    let init_ir_width: u32 = matches.value_of("ir-width").unwrap_or("800").parse().expect("getting IR initial width");
+    let init_ir_height: u32 = matches.value_of("ir-height").unwrap_or("600").parse().expect("getting IR initial height");
+    let init_rta_width: u32 = matches.value_of("rta-width").unwrap_or("800").parse().expect("getting RTA initial width");
+    let init_rta_height: u32 = matches.value_of("rta-height").unwrap_or("400").parse().expect("getting RTA initial height");
+    let init_chroma_width: u32 = matches.value_of("chroma-width").unwrap_or("480").parse().expect("getting chroma initial width");
+    let init_chroma_height: u32 = matches.value_of("chroma-height").unwrap_or("480").parse().expect("getting chroma initial height");
 
     let didx = if let Some(devname) = matches.value_of("aud-dev") {
         let (didx, _) = pa.devices().expect("listing devices").filter_map(Result::ok)
@@ -83,9 +112,10 @@ fn main() {
     };
 
     let info = pa.device_info(didx).expect("getting device info");
+    let calibration = Arc::new(Mutex::new(calibration::Calibration::load(info.name)));
 
     let params = Parameters::<f32>::new(
-        didx, 2, true, 0.0
+        didx, chan::INPUTS as i32, true, 0.0
     );
     let fpb: u32 = matches.value_of("aud-period").unwrap_or("256").parse().expect("getting audio period");
     let settings = InputSettings::new(
@@ -98,34 +128,83 @@ fn main() {
     println!("Settings: {:?}", settings);
     let fft_size: usize = matches.value_of("fft-size").unwrap_or("1024").parse().expect("getting FFT size");
     let mut fft_plan = rustfft::FftPlanner::new();
-    let fft = fft_plan.plan_fft_forward(fft_size);
+    let fft_len = fft_size * matches.value_of("fft-pad").unwrap_or("1").parse::<usize>().expect("getting FFT padding");
+    let fft = fft_plan.plan_fft_forward(fft_len);
     let mut fft_scratch: Vec<Complex<f32>> = iter::repeat(Complex { re: 0.0, im: 0.0 })
         .take(fft.get_inplace_scratch_len())
         .collect();
+    let mixes: Vec<chan::Mix> = if let Some(specs) = matches.values_of("chan") {
+        specs.map(|s| s.parse().expect("getting channel")).collect()
+    } else {
+        chan::defaults()
+    };
+    let mut cqt = if matches.is_present("spec-cqt") {
+        let rate = settings.sample_rate as f32;
+        Some(cqt::Cqt::new(
+            rate,
+            matches.value_of("cqt-min").unwrap_or("32.7").parse().expect("getting constant-Q minimum frequency"),
+            matches.value_of("cqt-max").unwrap_or("16000").parse().expect("getting constant-Q maximum frequency"),
+            matches.value_of("cqt-bins").unwrap_or("36").parse().expect("getting constant-Q bins per octave"),
+        ))
+    } else {
+        None
+    };
     let state = Arc::new(Mutex::new({
         let ci = ChannelInfo {
             scope: Fifo::new(init_sco_width as usize),
             win: Fifo::new(fft_size),
+            cq: Fifo::new(cqt.as_ref().map_or(0, |c| c.size())),
         };
         State {
-            left: ci.clone(),
-            right: ci,
+            channels: vec![ci; mixes.len()],
+            received: 0,
         }
     }));
+    // Only metered for the loudness view
+    let meter = if matches.is_present("loud") {
+        Some(Arc::new(Mutex::new(loudness::Meter::new(settings.sample_rate, chan::INPUTS))))
+    } else {
+        None
+    };
+    let ir_session = if matches.is_present("ir") {
+        let rate = settings.sample_rate;
+        let sweep = sweep::Sweep::new(
+            rate,
+            matches.value_of("ir-length").unwrap_or("5").parse().expect("getting sweep length"),
+            20.0, (rate * 0.45).min(20000.0),
+            10f64.powf(matches.value_of("ir-level").unwrap_or("-6").parse::<f64>().expect("getting sweep level") / 20.0),
+        ).expect("making sweep");
+        if let Some(path) = matches.value_of("ir-sweep-out") {
+            sweep::write_wav(path, rate, &sweep.signal).expect("writing sweep");
+        }
+        let tail: f64 = matches.value_of("ir-tail").unwrap_or("2").parse().expect("getting IR length");
+        let chan = if let Some(name) = matches.value_of("ir-chan") {
+            mixes.iter().position(|m| m.name == name).expect("finding IR channel")
+        } else {
+            0
+        };
+        Some((chan, Arc::new(Mutex::new(sweep::Session::new(sweep, tail, matches.is_present("ir-sweep-out"))))))
+    } else {
+        None
+    };
     let win = windows.get(matches.value_of("fft-win").unwrap_or("hann")).expect("getting window function")(fft_size);
+    let win = if fft_len > fft_size { window::pad(win, fft_len) } else { win };
     let mut stream = pa.open_non_blocking_stream(
         settings,
         {
             let st = state.clone();
+            let meter = meter.clone();
+            let mixes = mixes.clone();
+            let ir_session = ir_session.clone();
             let scale: f32 = matches.value_of("aud-scale").unwrap_or("1.0").parse().expect("getting audio scale");
             let mut scratch: Vec<f32> = Vec::with_capacity(32768);
             move |InputCallbackArgs {buffer, frames, ..}| {
                 let mut state = st.lock().unwrap();
-                assert_eq!(buffer.len(), frames * 2);
-                for offs in 0..=1 {
-                    let ifo = if offs == 0 { &mut state.left } else { &mut state.right };
+                assert_eq!(buffer.len(), frames * chan::INPUTS);
+                state.received += frames as u64;
+                for (idx, (ifo, mix)) in state.channels.iter_mut().zip(&mixes).enumerate() {
                     scratch.clear();
-                    scratch.extend(buffer.chunks(2).map(|s| s[offs]));
+                    scratch.extend(buffer.chunks(chan::INPUTS).map(|s| mix.apply(s)));
                     if scale != 1.0 {
                         for samp in scratch.iter_mut() {
                             *samp *= scale;
@@ -133,131 +212,428 @@ fn main() {
                     }
                     ifo.scope.push(&scratch);
                     ifo.win.push(&scratch);
+                    ifo.cq.push(&scratch);
+                    if let Some((_, sess)) = ir_session.as_ref().filter(|(chan, _)| *chan == idx) {
+                        sess.lock().unwrap().record(&scratch);
+                    }
+                }
+                drop(state);
+                if let Some(meter) = meter.as_ref() {
+                    let mut meter = meter.lock().unwrap();
+                    for frame in buffer.chunks(chan::INPUTS) {
+                        meter.push(&[frame[0] * scale, frame[1] * scale]);
+                    }
                 }
                 CallbackResult::Continue
             }
         },
     ).expect("opening stream");
 
+    // The sweep is played on the default output unless it was written out for
+    // external playback
+    let mut ir_stream = match &ir_session {
+        Some((_, sess)) if !matches.is_present("ir-sweep-out") => {
+            let odev = pa.default_output_device().expect("getting default output device");
+            let oinfo = pa.device_info(odev).expect("getting output device info");
+            let ochans = oinfo.max_output_channels.clamp(1, 2);
+            let oparams = Parameters::<f32>::new(odev, ochans, true, oinfo.default_low_output_latency);
+            let osettings = OutputSettings::new(oparams, settings.sample_rate, fpb);
+            let sess = sess.clone();
+            Some(pa.open_non_blocking_stream(
+                osettings,
+                move |OutputCallbackArgs {buffer, ..}| {
+                    sess.lock().unwrap().play(buffer, ochans as usize);
+                    CallbackResult::Continue
+                },
+            ).expect("opening sweep output stream"))
+        },
+        _ => None,
+    };
+
     let sdl = sdl2::init().expect("initializing SDL");
     let sdl_video = sdl.video().expect("initializing SDL video");
 
+    let mut placements = placement::Placements::load();
+    let mut tiles = if matches.is_present("tile") {
+        let layout = layout::Node::load(matches.value_of("layout").map(std::path::Path::new)).expect("loading layout");
+        let width = matches.value_of("tile-width").unwrap_or("1280").parse().expect("getting tiled window width");
+        let height = matches.value_of("tile-height").unwrap_or("800").parse().expect("getting tiled window height");
+        let (win, windowed) = placements.open(&sdl_video, "audec", width, height);
+        Some(layout::Tiles::new(win.into_canvas().build().expect("creating tiled canvas"), windowed, layout))
+    } else {
+        None
+    };
+    // A tile if the layout has a place for the view, else a window of its own
+    let mut open = |name: &str, width: u32, height: u32| match tiles.as_mut().filter(|t| t.wants(name)) {
+        Some(t) => t.pane(name),
+        None => {
+            let (win, windowed) = placements.open(&sdl_video, name, width, height);
+            Pane::new(win.into_canvas().build().expect("creating canvas"), name, windowed)
+        },
+    };
+
     let mut views: Vec<Box<dyn View>> = Vec::new();
+    // The deprecated --vec-brightness (0 to 255, 32 by default) and --vec-fade
+    // (the share of 255 faded each frame) stand in for these when not given
+    let phosphor_gain: f32 = match (matches.value_of("phosphor-gain"), matches.value_of("vec-brightness")) {
+        (None, Some(brightness)) => {
+            eprintln!("--vec-brightness is deprecated, use --phosphor-gain");
+            brightness.parse::<f32>().expect("getting vec brightness") / 8.0
+        },
+        (gain, _) => gain.unwrap_or("4").parse().expect("getting phosphor gain"),
+    };
+    let vec_persist: f32 = match (matches.value_of("vec-persist"), matches.value_of("vec-fade")) {
+        (None, Some(fade)) => {
+            eprintln!("--vec-fade is deprecated, use --vec-persist");
+            let frame_rate: f32 = matches.value_of("gfx-rate").unwrap_or("60").parse().expect("parsing frame rate");
+            let kept = 1.0 - fade.parse::<f32>().expect("getting vec fade").clamp(0.0, 255.0) / 255.0;
+            -1.0 / (frame_rate * kept.ln().min(-1e-3))
+        },
+        (persist, _) => persist.unwrap_or("0.1").parse().expect("getting vec persistence"),
+    };
+    let correction = matches.value_of("cal-curve")
+        .map(|path| Arc::new(weighting::Correction::load(path).expect("loading correction curve")));
+    let chan_pair = |arg: &str| {
+        let chan_index = |name: &str| mixes.iter().position(|m| m.name == name).expect("finding channel");
+        if let Some(mut names) = matches.values_of(arg) {
+            (chan_index(names.next().unwrap()), chan_index(names.next().expect("getting second channel")))
+        } else {
+            (0, std::cmp::min(1, mixes.len() - 1))
+        }
+    };
 
     if !matches.is_present("no-sco") {
-        let scope_win = sdl_video.window("scope", init_sco_width, init_sco_height)
-            .position_centered()
-            .resizable()
-            .build().expect("creating scope");
-        let scope_can = scope_win.into_canvas().build().expect("creating scope canvas");
+        let scope_pane = open("scope", init_sco_width, init_sco_height);
         let scope = view::scope::Scope {
-            view: scope_can,
+            view: scope_pane,
             zc_search: matches.value_of("sco-search").unwrap_or("1024").parse().expect("getting scope search"),
             zc_horiz: matches.value_of("sco-pos").unwrap_or("0.5").parse().expect("getting scope zc pos"),
             pow: matches.value_of("sco-pow").unwrap_or("1.0").parse().expect("getting scope pow"),
+            timebase: matches.value_of("sco-time").map(|t| view::scope::parse_time(t).expect("getting scope timebase")),
+            div: view::scope::parse_div(matches.value_of("sco-div").unwrap_or("0.25")).expect("getting scope vertical scale"),
+            envelope: !matches.is_present("sco-no-envelope"),
+            layout: matches.value_of("sco-layout").unwrap_or("overlay").parse().expect("getting scope layout"),
+            phosphor: view::phosphor::Phosphor::new(
+                matches.value_of("sco-persist").unwrap_or("0.05").parse().expect("getting scope persistence"),
+                phosphor_gain,
+            ),
+            use_phosphor: matches.is_present("sco-persist"),
+            heat: matches.is_present("phosphor-heat"),
+            divs: Vec::new(),
+            selected: 0,
+            rate: settings.sample_rate as f32,
+            lock: matches.is_present("sco-lock") || matches.is_present("sco-periods"),
+            periods: matches.value_of("sco-periods").map(|n| n.parse().expect("getting scope periods")),
+            period: None,
+            trigger: {
+                let mode: view::scope::Mode = matches.value_of("sco-trig-mode").unwrap_or("auto").parse().expect("getting trigger mode");
+                view::scope::Trigger {
+                    source: matches.value_of("sco-trig").map(|name| {
+                        mixes.iter().position(|m| m.name == name).expect("finding trigger channel")
+                    }),
+                    edge: matches.value_of("sco-trig-edge").unwrap_or("rising").parse().expect("getting trigger edge"),
+                    level: matches.value_of("sco-trig-level").unwrap_or("0").parse().expect("getting trigger level"),
+                    hysteresis: matches.value_of("sco-trig-hyst").unwrap_or("0.01").parse().expect("getting trigger hysteresis"),
+                    holdoff: matches.value_of("sco-trig-holdoff").unwrap_or("0").parse::<f32>().expect("getting trigger holdoff") / 1000.0,
+                    mode,
+                    armed: mode == view::scope::Mode::Single,
+                    last: None,
+                }
+            },
+            held: Vec::new(),
+            channels: mixes.len(),
+            title: String::new(),
         };
         views.push(Box::new(scope));
     }
 
     if !matches.is_present("no-spec") {
-        let spec_win = sdl_video.window("spec", init_spec_width, init_spec_height)
-            .position_centered()
-            .resizable()
-            .build().expect("creating spec");
-        let spec_can = spec_win.into_canvas().build().expect("creating spec canvas");
+        let spec_pane = open("spec", init_spec_width, init_spec_height);
         let spec = view::spec::Spec {
-            view: spec_can,
+            view: spec_pane,
             db_bias: matches.value_of("spec-bias").unwrap_or("-5.0").parse().expect("getting spectrogram bias"),
             db_range: matches.value_of("spec-range").unwrap_or("30.0").parse().expect("getting spectrogram range"),
             waterfall_sz: matches.value_of("spec-water-size").unwrap_or("0.8").parse().expect("getting spectrogam waterfall size"),
             waterfall_data: None,
-            waterfall_tex: std::ptr::null_mut(),
+            waterfall_tex: None,
+            waterfall_head: 0,
+            harmonics: matches.value_of("spec-harm").unwrap_or("10").parse().expect("getting harmonic count"),
+            show_harmonics: matches.is_present("spec-harm"),
+            weighting: weighting::Weighting {
+                curve: matches.value_of("spec-weight").unwrap_or("z").parse().expect("getting spectrogram weighting"),
+                correction: correction.clone(),
+            },
+            weights: Vec::new(),
+            harm_chan: 0,
+            spl: matches.is_present("spec-spl"),
+            constant_q: matches.is_present("spec-cqt"),
+            cq_weights: Vec::new(),
+            waterfall: if matches.is_present("spec-reassign") {
+                view::spec::Waterfall::Reassigned
+            } else {
+                view::spec::Waterfall::Plain
+            },
+            can_reassign: matches.is_present("spec-reassign"),
+            last_frame: None,
+            show_peaks: matches.is_present("peaks"),
+            title: String::new(),
+            error: None,
         };
         views.push(Box::new(spec));
     }
 
     if !matches.is_present("no-vec") {
-        let vec_win = sdl_video.window("vec", init_vec_width, init_vec_height)
-            .position_centered()
-            .resizable()
-            .build().expect("creating vec");
-        let vec_can = vec_win.into_canvas().build().expect("creating vec canvas");
+        let vec_pane = open("vec", init_vec_width, init_vec_height);
+        let chans = chan_pair("vec-chans");
         let vec = view::vec::Vector {
-            view: vec_can,
-            fade_rate: matches.value_of("vec-fade").unwrap_or("32").parse().expect("getting vec fade"),
-            brightness: matches.value_of("vec-brightness").unwrap_or("32").parse().expect("getting vec brightness"),
+            view: vec_pane,
+            chans,
+            phosphor: view::phosphor::Phosphor::new(vec_persist, phosphor_gain),
+            heat: matches.is_present("phosphor-heat"),
+            mode: matches.value_of("vec-mode").unwrap_or("xy").parse().expect("getting vec mode"),
+            auto_gain: matches.is_present("vec-auto"),
+            compensate: matches.is_present("delay-comp"),
+            envelope: 0.0,
+            envelope_at: None,
+            meter: {
+                let time: f32 = matches.value_of("vec-corr-time").unwrap_or("0.3").parse().expect("getting vec correlation time");
+                if matches.is_present("vec-no-meter") { None } else { Some(view::vec::StereoMeter::new(time)) }
+            },
+            title: String::new(),
         };
         views.push(Box::new(vec));
     }
 
+    if let Some(meter) = &meter {
+        let loud_pane = open("loud", init_loud_width, init_loud_height);
+        let loud = view::loud::Loudness {
+            view: loud_pane,
+            meter: meter.clone(),
+            target: matches.value_of("loud-target").unwrap_or("-23").parse().expect("getting loudness target"),
+            range: matches.value_of("loud-range").unwrap_or("48").parse().expect("getting loudness range"),
+            weighting: weighting::Weighting {
+                curve: matches.value_of("loud-weight").unwrap_or("a").parse().expect("getting loudness meter weighting"),
+                correction: correction.clone(),
+            },
+            weights: Vec::new(),
+            title: String::new(),
+        };
+        views.push(Box::new(loud));
+    }
+
+    if matches.is_present("xfer") {
+        let xfer_pane = open("xfer", init_xfer_width, init_xfer_height);
+        let (reference, measure) = chan_pair("xfer-chans");
+        let xfer = view::xfer::Transfer {
+            view: xfer_pane,
+            reference,
+            measure,
+            averages: matches.value_of("xfer-avg").unwrap_or("16").parse().expect("getting transfer averages"),
+            db_range: matches.value_of("xfer-range").unwrap_or("48").parse().expect("getting transfer range"),
+            compensate: matches.is_present("delay-comp"),
+            sxx: Vec::new(),
+            syy: Vec::new(),
+            sxy: Vec::new(),
+        };
+        views.push(Box::new(xfer));
+    }
+
+    if matches.is_present("delay") {
+        let delay_pane = open("delay", init_delay_width, init_delay_height);
+        let delay = view::delay::Delay {
+            view: delay_pane,
+            range: matches.value_of("delay-range").unwrap_or("10").parse().expect("getting delay range"),
+            title: String::new(),
+        };
+        views.push(Box::new(delay));
+    }
+
+    if let Some((_, sess)) = &ir_session {
+        let ir_pane = open("ir", init_ir_width, init_ir_height);
+        let ir = view::ir::Impulse {
+            view: ir_pane,
+            session: sess.clone(),
+            out_path: matches.value_of("ir-out").unwrap_or("ir.wav").into(),
+            analysis: view::ir::Analysis::default(),
+            analyzing: None,
+            title: String::new(),
+        };
+        views.push(Box::new(ir));
+    }
+
+    if matches.is_present("rta") {
+        let rta_pane = open("rta", init_rta_width, init_rta_height);
+        let rta = view::rta::Rta {
+            view: rta_pane,
+            fraction: view::rta::parse_fraction(matches.value_of("rta-frac").unwrap_or("3")).expect("getting RTA fraction"),
+            weighting: weighting::Weighting {
+                curve: matches.value_of("rta-weight").unwrap_or("z").parse().expect("getting RTA weighting"),
+                correction: correction.clone(),
+            },
+            db_range: matches.value_of("rta-range").unwrap_or("90").parse().expect("getting RTA range"),
+            bands: Vec::new(),
+            weights: Vec::new(),
+            levels: Vec::new(),
+            calibration: calibration.clone(),
+            cal_freq: matches.value_of("cal-freq").unwrap_or("1000").parse().expect("getting calibrator frequency"),
+            cal_level: matches.value_of("cal-level").unwrap_or("94").parse().expect("getting calibrator level"),
+            calibrating: None,
+            cal_result: None,
+            title: String::new(),
+        };
+        views.push(Box::new(rta));
+    }
+
+    if matches.is_present("chroma") {
+        let chroma_pane = open("chroma", init_chroma_width, init_chroma_height);
+        let chroma = view::chroma::Chroma {
+            view: chroma_pane,
+            key_time: matches.value_of("chroma-key-time").unwrap_or("10").parse().expect("getting key estimate time"),
+            history: std::collections::VecDeque::new(),
+            average: [0.0; 12],
+            last: None,
+            title: String::new(),
+        };
+        views.push(Box::new(chroma));
+    }
+
+    let mut finder = if matches.is_present("delay") || matches.is_present("delay-comp") {
+        let (reference, measure) = chan_pair("delay-chans");
+        Some(delay::Finder::new(
+            fft_size, reference, measure,
+            matches.is_present("delay-phat"),
+            matches.value_of("delay-avg").unwrap_or("8").parse().expect("getting delay averages"),
+        ))
+    } else {
+        None
+    };
+
     let mut eloop = sdl.event_pump().expect("creating event loop");
     let mut deadline;
-    let mut lspec: Vec<Complex<f32>> = vec![Complex { re: 0f32, im: 0f32 }; fft_size];
-    let mut rspec: Vec<Complex<f32>> = vec![Complex { re: 0f32, im: 0f32 }; fft_size];
+    let mut specs: Vec<Vec<Complex<f32>>> = vec![vec![Complex { re: 0f32, im: 0f32 }; fft_len]; mixes.len()];
+    let mut taken = vec![0u64; mixes.len()];
+    let mut peaks: Vec<Vec<peaks::Peak>> = vec![Vec::new(); mixes.len()];
+    let mut trackers: Vec<peaks::Tracker> = match matches.value_of("peaks") {
+        Some(count) => {
+            let count = count.parse().expect("getting peak count");
+            let method: peaks::Method = matches.value_of("peak-method").unwrap_or("gauss").parse().expect("getting peak method");
+            let floor = matches.value_of("peak-floor").unwrap_or("60").parse().expect("getting peak floor");
+            (0 .. mixes.len()).map(|_| peaks::Tracker::new(method, count, floor)).collect()
+        },
+        None => Vec::new(),
+    };
+    let mut cqs: Vec<Vec<f32>> = vec![Vec::new(); mixes.len()];
+    let mut points: Vec<Vec<reassign::Point>> = vec![Vec::new(); mixes.len()];
+    let mut reassigner = if matches.is_present("spec-reassign") {
+        Some(reassign::Reassigner::new(&*win))
+    } else {
+        None
+    };
     let rate = Duration::new(1, 0).div_f64(matches.value_of("gfx-rate").unwrap_or("60").parse::<f64>().expect("parsing frame rate"));
     stream.start().expect("starting stream");
+    if let Some(s) = ir_stream.as_mut() {
+        s.start().expect("starting sweep output stream");
+    }
     'main: loop {
         deadline = Instant::now() + rate;
         hprof::start_frame();
 
         {
-            for i in 0 ..= 1 {
-                let slc = {
+            for (i, slc) in specs.iter_mut().enumerate() {
+                {
                     let st = state.lock().unwrap();
-                    if i == 0 {
-                        lspec.clear();
-                        lspec.extend(st.left.win.iter().map(|&x| Complex { re: x, im: 0.0 }));
-                        &mut lspec
-                    } else {
-                        rspec.clear();
-                        rspec.extend(st.right.win.iter().map(|&x| Complex { re: x, im: 0.0 }));
-                        &mut rspec
-                    }
-                };
+                    slc.clear();
+                    slc.extend(st.channels[i].win.iter().map(|&x| Complex { re: x, im: 0.0 }));
+                    taken[i] = st.received;
+                }
+                slc.resize(fft_len, Complex { re: 0.0, im: 0.0 });
 
                 for (pt, wv) in slc.iter_mut().zip(win.shape()) {
                     *pt *= wv;
                 }
 
                 fft.process_with_scratch(slc, &mut fft_scratch);
-                let fac = 1f32 / (slc.len() as f32).sqrt();
-                for pt in slc {
+                // By the unpadded length; see Window::power_scale
+                let fac = 1f32 / (fft_size as f32).sqrt();
+                for pt in slc.iter_mut() {
                     *pt *= fac;
                 }
             }
         }
 
+        for (((tracker, spec), &at), out) in trackers.iter_mut().zip(&specs).zip(&taken).zip(peaks.iter_mut()) {
+            tracker.find(spec, settings.sample_rate as f32, &*win, at, out);
+        }
+
         let stcopy = {
             let st = state.lock().unwrap();
             st.clone()
         };
 
+        if let Some(r) = reassigner.as_mut() {
+            for (ci, out) in stcopy.channels.iter().zip(points.iter_mut()) {
+                r.process(&ci.win, out);
+            }
+        }
+
+        if let Some(c) = cqt.as_mut() {
+            for (ci, out) in stcopy.channels.iter().zip(cqs.iter_mut()) {
+                c.process(&ci.cq, out);
+            }
+        }
+
+        if let Some(f) = finder.as_mut() {
+            let _g = hprof::enter("delay");
+            let (r, m) = (f.estimate.reference, f.estimate.measure);
+            f.update(&stcopy.channels[r].win, &stcopy.channels[m].win);
+        }
+
+        let offsets: Vec<Option<f32>> = {
+            let cal = calibration.lock().unwrap();
+            mixes.iter().map(|m| cal.offset(&m.name)).collect()
+        };
+
         let info = view::Info {
-            left: view::ChannelInfo {
-                samples: &stcopy.left.scope[..],
-                spectrum: &lspec[..],
-            },
-            right: view::ChannelInfo {
-                samples: &stcopy.right.scope[..],
-                spectrum: &rspec[..],
-            },
+            channels: stcopy.channels.iter().zip(&specs).zip(&cqs).zip(&points).zip(&peaks).zip(&mixes).zip(&offsets)
+                .map(|((((((ci, spec), cq), points), peaks), mix), &spl)| view::ChannelInfo {
+                    name: &mix.name,
+                    samples: &ci.scope[..],
+                    spectrum: &spec[..],
+                    cq: &cq[..],
+                    reassigned: &points[..],
+                    peaks: &peaks[..],
+                    spl,
+                })
+                .collect(),
+            rate: settings.sample_rate as f32,
+            received: stcopy.received,
+            window: &*win,
+            delay: finder.as_ref().map(|f| &f.estimate),
+            cqt: cqt.as_ref(),
             sdl: view::SDLInfo {
                 ctx: &sdl,
                 eloop: &eloop,
             },
         };
 
+        if let Some(t) = tiles.as_mut() {
+            t.begin();
+        }
         let mut winsz = MIN_SAMPS;
         for view in &mut views {
             view.render(&info);
             winsz = std::cmp::max(winsz, view.requested_window());
         }
+        if let Some(t) = tiles.as_mut() {
+            t.present();
+        }
 
         {
             let mut st = state.lock().unwrap();
-            if winsz != st.left.scope.size() {
-                st.left.scope.resize(winsz);
-                st.right.scope.resize(winsz);
+            if winsz != st.channels[0].scope.size() {
+                for ci in st.channels.iter_mut() {
+                    ci.scope.resize(winsz);
+                }
             }
         }
 
@@ -270,6 +646,31 @@ fn main() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'main,
+                Event::KeyDown {
+                    window_id,
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => match tiles.as_mut().filter(|t| t.window_id() == window_id) {
+                    Some(t) => t.toggle_fullscreen(),
+                    None => for view in views.iter_mut().filter(|v| v.window_id() == window_id) {
+                        view.pane_mut().toggle_fullscreen();
+                    },
+                },
+                Event::KeyDown {
+                    window_id,
+                    keycode: Some(key),
+                    ..
+                } => {
+                    let window_id = tiles.as_ref().map_or(window_id, |t| t.route(window_id));
+                    for view in views.iter_mut().filter(|v| v.window_id() == window_id) {
+                        view.key(key);
+                    }
+                },
+                Event::MouseMotion { window_id, x, y, .. } => {
+                    if let Some(t) = tiles.as_mut() {
+                        t.motion(window_id, x, y);
+                    }
+                },
                 _ => (),
             }
         }
@@ -280,5 +681,15 @@ fn main() {
         }
         // println!("tick");
     }
+
+    for (name, placement) in views.iter().filter_map(|v| v.pane().placement()) {
+        placements.set(name, placement);
+    }
+    if let Some(t) = &tiles {
+        placements.set("audec", t.placement());
+    }
+    if let Err(e) = placements.save() {
+        eprintln!("saving window placements: {}", e);
+    }
     hprof::profiler().print_timing();
 }
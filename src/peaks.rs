@@ -0,0 +1,172 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use rustfft::num_complex::Complex;
+
+use crate::window::Window;
+
+/// How a peak's frequency is refined between bins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Parabola through the magnitudes of the peak bin and its neighbors
+    Quadratic,
+    /// Parabola through their log magnitudes (exact for a Gaussian window,
+    /// close for most others)
+    Gaussian,
+    /// Instantaneous frequency from the phase advance since the last frame
+    PhaseVocoder,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Method, String> {
+        match s {
+            "quad" => Ok(Method::Quadratic),
+            "gauss" => Ok(Method::Gaussian),
+            "pv" => Ok(Method::PhaseVocoder),
+            _ => Err(format!("unknown peak method {} (expected quad, gauss or pv)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Peak {
+    pub freq: f32,
+    /// Level of the sine that would make this peak (a full-scale sine is
+    /// 0dB), in dB
+    pub level: f32,
+}
+
+/// Finds the strongest peaks of one channel's spectrum, frame after frame
+/// (the phase vocoder needs the last one).
+pub struct Tracker {
+    pub method: Method,
+    pub count: usize,
+    /// How far below the strongest peak others are still reported, in dB
+    pub floor: f32,
+    prev: Vec<Complex<f32>>,
+    prev_at: u64,
+}
+
+impl Tracker {
+    pub fn new(method: Method, count: usize, floor: f32) -> Tracker {
+        Tracker { method, count, floor, prev: Vec::new(), prev_at: 0 }
+    }
+
+    /// Peaks of `spectrum` (as computed by `main`), strongest first. `at` is
+    /// the number of samples received when it was taken.
+    pub fn find(&mut self, spectrum: &[Complex<f32>], rate: f32, window: &dyn Window, at: u64, out: &mut Vec<Peak>) {
+        let _g = hprof::enter("Tracker::find");
+        let n = spectrum.len();
+        let half = n / 2;
+        let mag = |k: usize| spectrum[k].norm();
+        let strongest = (1 .. half).map(mag).fold(0f32, f32::max);
+        let threshold = strongest * 10f32.powf(-self.floor / 20.0);
+
+        let mut bins: Vec<usize> = (2 .. half - 1)
+            .filter(|&k| mag(k) > threshold && mag(k) > mag(k - 1) && mag(k) >= mag(k + 1))
+            .collect();
+        bins.sort_by(|&a, &b| mag(b).total_cmp(&mag(a)));
+        bins.truncate(self.count);
+
+        // A sine of amplitude A peaks at |X|^2 = A^2 / (2 * power_scale * ENBW)
+        let to_db = |m: f32| 10.0 * (2.0 * window.power_scale() * window.enbw() * m * m).max(1e-20).log10();
+        let hop = at.wrapping_sub(self.prev_at);
+        let vocode = self.method == Method::PhaseVocoder && self.prev.len() == n && hop > 0 && hop < n as u64;
+        out.clear();
+        for k in bins {
+            let (a, b, c) = (mag(k - 1), mag(k), mag(k + 1));
+            let (delta, peak) = if self.method == Method::Quadratic {
+                let den = a - 2.0 * b + c;
+                let delta = if den.abs() > f32::EPSILON { 0.5 * (a - c) / den } else { 0.0 };
+                (delta, b - 0.25 * (a - c) * delta)
+            } else {
+                let (a, b, c) = (a.max(1e-30).ln(), b.max(1e-30).ln(), c.max(1e-30).ln());
+                let den = a - 2.0 * b + c;
+                let delta = if den.abs() > f32::EPSILON { 0.5 * (a - c) / den } else { 0.0 };
+                (delta, (b - 0.25 * (a - c) * delta).exp())
+            };
+            let delta = if vocode {
+                // The phase advance beyond what bin k's own frequency accounts
+                // for, wrapped, is the offset from it
+                let expected = 2.0 * PI * k as f32 * hop as f32 / n as f32;
+                let advance = spectrum[k].arg() - self.prev[k].arg() - expected;
+                let wrapped = advance - 2.0 * PI * (advance / (2.0 * PI)).round();
+                wrapped * n as f32 / (2.0 * PI * hop as f32)
+            } else {
+                delta
+            };
+            out.push(Peak {
+                freq: (k as f32 + delta) * rate / n as f32,
+                level: to_db(peak),
+            });
+        }
+
+        self.prev.clear();
+        self.prev.extend_from_slice(spectrum);
+        self.prev_at = at;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window;
+
+    use rustfft::FftPlanner;
+
+    const RATE: f32 = 48000.0;
+    const SIZE: usize = 4096;
+
+    /// Sines of the (frequency, amplitude) `tones`, from sample `start`,
+    /// windowed and scaled as `main` does.
+    fn spectrum(tones: &[(f32, f32)], start: usize, window: &dyn Window) -> Vec<Complex<f32>> {
+        let mut buf: Vec<Complex<f32>> = (0 .. SIZE).zip(window.shape())
+            .map(|(n, w)| {
+                let t = (start + n) as f32 / RATE;
+                let s: f32 = tones.iter().map(|&(f, a)| a * (2.0 * PI * f * t).sin()).sum();
+                Complex { re: s * w, im: 0.0 }
+            })
+            .collect();
+        FftPlanner::new().plan_fft_forward(SIZE).process(&mut buf);
+        let fac = 1.0 / (SIZE as f32).sqrt();
+        buf.iter().map(|c| c * fac).collect()
+    }
+
+    #[test]
+    fn interpolates_between_bins() {
+        let hann = window::windows()["hann"](SIZE);
+        let bin = RATE / SIZE as f32;
+        let freq = 1000.0 + 0.3 * bin;
+        let spec = spectrum(&[(freq, 0.5)], 0, &*hann);
+        for (method, tol) in [(Method::Quadratic, 0.1), (Method::Gaussian, 0.03)] {
+            let mut out = Vec::new();
+            Tracker::new(method, 1, 60.0).find(&spec, RATE, &*hann, SIZE as u64, &mut out);
+            assert_eq!(out.len(), 1);
+            assert!((out[0].freq - freq).abs() < tol * bin, "{:?}: {}", method, out[0].freq);
+            assert!((out[0].level + 6.02).abs() < 0.5, "{:?}: {}", method, out[0].level);
+        }
+    }
+
+    #[test]
+    fn phase_vocoder_refines_over_two_frames() {
+        let hann = window::windows()["hann"](SIZE);
+        let freq = 1234.56;
+        let mut tracker = Tracker::new(Method::PhaseVocoder, 1, 60.0);
+        let mut out = Vec::new();
+        tracker.find(&spectrum(&[(freq, 0.5)], 0, &*hann), RATE, &*hann, SIZE as u64, &mut out);
+        tracker.find(&spectrum(&[(freq, 0.5)], 256, &*hann), RATE, &*hann, SIZE as u64 + 256, &mut out);
+        assert!((out[0].freq - freq).abs() < 0.05, "{}", out[0].freq);
+    }
+
+    #[test]
+    fn strongest_first_down_to_the_floor() {
+        let hann = window::windows()["hann"](SIZE);
+        let spec = spectrum(&[(500.0, 0.1), (3000.0, 0.5), (8000.0, 0.0001)], 0, &*hann);
+        let mut out = Vec::new();
+        Tracker::new(Method::Gaussian, 5, 40.0).find(&spec, RATE, &*hann, SIZE as u64, &mut out);
+        assert_eq!(out.len(), 2, "{:?}", out);
+        assert!((out[0].freq - 3000.0).abs() < 5.0 && (out[1].freq - 500.0).abs() < 5.0, "{:?}", out);
+    }
+}
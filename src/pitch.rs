@@ -0,0 +1,112 @@
+pub const MIN_PITCH: f32 = 40.0;
+pub const MAX_PITCH: f32 = 2000.0;
+/// The first autocorrelation peak this close to the highest is the period,
+/// rather than the highest itself (which may be a multiple of it)
+const PEAK_RATIO: f32 = 0.9;
+/// Below this the signal isn't considered periodic
+const CLARITY: f32 = 0.5;
+const MAX_WINDOW: usize = 2048;
+
+/// Longest lag examined at `rate`, in samples.
+pub fn max_lag(rate: f32) -> usize {
+    (rate / MIN_PITCH).ceil() as usize
+}
+
+/// The fundamental period of the most recent part of `samples`, in
+/// (fractional) samples, from its normalized square difference function
+/// (McLeod and Wyvill), if it is periodic between MIN_PITCH and MAX_PITCH.
+pub fn period(samples: &[f32], rate: f32) -> Option<f32> {
+    let _g = hprof::enter("pitch::period");
+    let (min_lag, max_lag) = ((rate / MAX_PITCH).floor() as usize, max_lag(rate));
+    let window = std::cmp::min(samples.len().checked_sub(max_lag)?, MAX_WINDOW);
+    if window < min_lag {
+        return None;
+    }
+    let data = &samples[samples.len() - window - max_lag ..];
+    let nsdf: Vec<f32> = (0 ..= max_lag)
+        .map(|lag| {
+            let (mut acf, mut energy) = (0f32, 0f32);
+            for i in 0 .. window {
+                let (a, b) = (data[i], data[i + lag]);
+                acf += a * b;
+                energy += a * a + b * b;
+            }
+            if energy <= f32::EPSILON { 0.0 } else { 2.0 * acf / energy }
+        })
+        .collect();
+
+    // The maxima between positive-going and negative-going zero crossings,
+    // past the first lobe around zero lag
+    let mut peaks = Vec::new();
+    let mut start = nsdf.iter().position(|&n| n < 0.0)?;
+    while start < max_lag {
+        let end = match nsdf[start ..].iter().position(|&n| n >= 0.0) {
+            Some(up) => match nsdf[start + up ..].iter().position(|&n| n < 0.0) {
+                Some(down) => (start + up, start + up + down),
+                None => (start + up, max_lag + 1),
+            },
+            None => break,
+        };
+        let best = (end.0 .. end.1).max_by(|&a, &b| nsdf[a].total_cmp(&nsdf[b])).unwrap();
+        if best >= min_lag && best > 0 && best < max_lag {
+            peaks.push(best);
+        }
+        start = end.1;
+    }
+    let highest = peaks.iter().map(|&p| nsdf[p]).fold(f32::NEG_INFINITY, f32::max);
+    if highest < CLARITY {
+        return None;
+    }
+    let lag = *peaks.iter().find(|&&p| nsdf[p] >= PEAK_RATIO * highest)?;
+
+    let (a, b, c) = (nsdf[lag - 1], nsdf[lag], nsdf[lag + 1]);
+    let den = a - 2.0 * b + c;
+    let delta = if den.abs() > f32::EPSILON { 0.5 * (a - c) / den } else { 0.0 };
+    Some(lag as f32 + delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32::consts::PI;
+
+    const RATE: f32 = 48000.0;
+
+    fn tone(freq: f32, amps: &[f32], len: usize) -> Vec<f32> {
+        (0 .. len)
+            .map(|n| {
+                let t = n as f32 / RATE;
+                amps.iter().enumerate().map(|(k, a)| a * (2.0 * PI * freq * (k + 1) as f32 * t).sin()).sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_a_sine_period() {
+        for freq in [55.0, 220.0, 1500.0] {
+            let p = period(&tone(freq, &[0.5], 4096), RATE).unwrap();
+            assert!((p / (RATE / freq) - 1.0).abs() < 0.005, "{} Hz: {}", freq, p);
+        }
+    }
+
+    #[test]
+    fn finds_the_fundamental_under_harmonics() {
+        let p = period(&tone(110.0, &[1.0, 0.6, 0.4, 0.3], 4096), RATE).unwrap();
+        assert!((p - RATE / 110.0).abs() < 0.5, "{}", p);
+    }
+
+    #[test]
+    fn no_period_without_one() {
+        assert_eq!(period(&[0.0; 4096], RATE), None);
+        assert_eq!(period(&tone(220.0, &[0.5], 100), RATE), None);
+        let mut state = 12345u32;
+        let noise: Vec<f32> = (0 .. 4096)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+        assert_eq!(period(&noise, RATE), None);
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use sdl2::VideoSubsystem;
+use sdl2::video::{FullscreenType, Window};
+
+use crate::config;
+
+const FILE: &str = "windows";
+
+/// Where a window is on the desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub display: i32,
+    pub fullscreen: bool,
+}
+
+impl Placement {
+    /// Where `window` is now. A fullscreen window's size and position are the
+    /// screen's, so for one those are kept from `windowed`, where it was last
+    /// seen otherwise.
+    pub fn of(window: &Window, windowed: &Placement) -> Placement {
+        let display = window.display_index().unwrap_or(windowed.display);
+        if window.fullscreen_state() != FullscreenType::Off {
+            return Placement { display, fullscreen: true, ..*windowed };
+        }
+        let ((x, y), (width, height)) = (window.position(), window.size());
+        Placement { x, y, width, height, display, fullscreen: false }
+    }
+
+    /// Switch `window` in or out of (desktop) fullscreen, remembering where it
+    /// was in `windowed`.
+    pub fn toggle_fullscreen(window: &mut Window, windowed: &mut Placement) {
+        *windowed = Placement::of(window, windowed);
+        let state = if windowed.fullscreen { FullscreenType::Off } else { FullscreenType::Desktop };
+        window.set_fullscreen(state).expect("toggling fullscreen");
+    }
+}
+
+/// Where each window (by name) was last, persisted in the config directory
+/// as tab-separated "name, x, y, width, height, display, fullscreen" lines.
+#[derive(Debug, Clone, Default)]
+pub struct Placements {
+    windows: HashMap<String, Placement>,
+}
+
+impl Placements {
+    pub fn load() -> Placements {
+        let mut placements = Placements::default();
+        let text = match config::path(FILE).map(fs::read_to_string) {
+            Some(Ok(t)) => t,
+            _ => return placements,
+        };
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if let [name, rest @ ..] = &fields[..] {
+                if let Some(p) = Placements::parse(rest) {
+                    placements.windows.insert(name.to_string(), p);
+                }
+            }
+        }
+        placements
+    }
+
+    fn parse(fields: &[&str]) -> Option<Placement> {
+        match fields {
+            [x, y, width, height, display, fullscreen] => Some(Placement {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+                width: width.parse().ok()?,
+                height: height.parse().ok()?,
+                display: display.parse().ok()?,
+                fullscreen: fullscreen.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = config::save_path(FILE)?;
+        let mut lines: Vec<String> = self.windows.iter()
+            .map(|(name, p)| format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                name, p.x, p.y, p.width, p.height, p.display, p.fullscreen,
+            ))
+            .collect();
+        lines.sort();
+        fs::write(&path, lines.concat())?;
+        Ok(path)
+    }
+
+    pub fn set(&mut self, name: &str, placement: Placement) {
+        self.windows.insert(name.into(), placement);
+    }
+
+    /// Open the window `name` where it was last (centered on its display if
+    /// that has changed so it's out of sight), or centered at `width` by
+    /// `height` the first time. Also returns where it is when windowed.
+    pub fn open(&self, video: &VideoSubsystem, name: &str, width: u32, height: u32) -> (Window, Placement) {
+        let saved = self.windows.get(name)
+            .filter(|p| p.display < video.num_video_displays().unwrap_or(1));
+        let (width, height) = saved.map_or((width, height), |p| (p.width, p.height));
+        let mut builder = video.window(name, width, height);
+        match saved.map(|p| (p, video.display_bounds(p.display))) {
+            Some((p, Ok(bounds))) if bounds.contains_point((p.x, p.y)) => builder.position(p.x, p.y),
+            Some((_, Ok(bounds))) => builder.position(
+                bounds.x() + (bounds.width() as i32 - width as i32) / 2,
+                bounds.y() + (bounds.height() as i32 - height as i32) / 2,
+            ),
+            _ => builder.position_centered(),
+        };
+        let mut window = builder.resizable().build().expect("creating window");
+        let windowed = Placement::of(&window, &Placement {
+            x: 0, y: 0, width, height, display: 0, fullscreen: false,
+        });
+        if saved.is_some_and(|p| p.fullscreen) {
+            window.set_fullscreen(FullscreenType::Desktop).expect("going fullscreen");
+        }
+        (window, windowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_saved_fields() {
+        assert_eq!(Placements::parse(&["-20", "40", "800", "600", "1", "true"]), Some(Placement {
+            x: -20, y: 40, width: 800, height: 600, display: 1, fullscreen: true,
+        }));
+    }
+
+    #[test]
+    fn skips_malformed_fields() {
+        for fields in [
+            &["0", "0", "800", "600", "0"][..],
+            &["0", "0", "800", "600", "0", "false", "extra"],
+            &["0", "0", "-800", "600", "0", "false"],
+            &["0", "0", "800", "600", "0", "yes"],
+            &["left", "0", "800", "600", "0", "false"],
+        ] {
+            assert_eq!(Placements::parse(fields), None, "{:?}", fields);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use rustfft::{Fft, FftPlanner};
+use rustfft::num_complex::Complex;
+
+use crate::window::Window;
+
+// Time-frequency reassignment (Auger and Flandrin): alongside the usual
+// windowed FFT, transforms with the window's derivative and its time-weighted
+// copy give, for each bin, where in frequency and time its energy is actually
+// centered. Moving the energy there sharpens tones, chirps and transients far
+// beyond the window's own resolution.
+
+/// Bins weaker than this (in the units of `Point::power`) aren't reassigned.
+const FLOOR: f32 = 1e-12;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Point {
+    /// Reassigned frequency, in (fractional) bins
+    pub bin: f32,
+    /// Reassigned time, in samples from the center of the window (negative
+    /// is older)
+    pub time: f32,
+    /// Squared magnitude, scaled as `main`'s spectrum (by 1/N, N being the
+    /// number of samples)
+    pub power: f32,
+}
+
+pub struct Reassigner {
+    fft: Arc<dyn Fft<f32>>,
+    shape: Vec<f32>,
+    dshape: Vec<f32>,
+    tshape: Vec<f32>,
+    bufs: [Vec<Complex<f32>>; 3],
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Reassigner {
+    pub fn new(window: &dyn Window) -> Reassigner {
+        let size = window.size();
+        let fft = FftPlanner::new().plan_fft_forward(size);
+        let scratch = vec![Complex { re: 0.0, im: 0.0 }; fft.get_inplace_scratch_len()];
+        let buf = vec![Complex { re: 0.0, im: 0.0 }; size];
+        Reassigner {
+            fft,
+            shape: window.shape().to_vec(),
+            dshape: window.derivative(),
+            tshape: window.time_weighted(),
+            bufs: [buf.clone(), buf.clone(), buf],
+            scratch,
+        }
+    }
+
+    /// Reassign each positive-frequency bin of the (unwindowed) `samples`,
+    /// zero-padded to the window's size.
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<Point>) {
+        let _g = hprof::enter("Reassigner::process");
+        let size = self.shape.len();
+        assert!(samples.len() <= size);
+        for (buf, shape) in self.bufs.iter_mut().zip([&self.shape, &self.dshape, &self.tshape]) {
+            for (idx, (b, &w)) in buf.iter_mut().zip(shape.iter()).enumerate() {
+                *b = Complex { re: samples.get(idx).map_or(0.0, |s| s * w), im: 0.0 };
+            }
+            self.fft.process_with_scratch(buf, &mut self.scratch);
+        }
+
+        let [xh, xdh, xth] = &self.bufs;
+        let binwidth = size as f32 / (2.0 * std::f32::consts::PI);
+        out.clear();
+        for k in 1 .. size / 2 {
+            let power = xh[k].norm_sqr() / samples.len() as f32;
+            if power < FLOOR {
+                continue;
+            }
+            let inv = xh[k].conj() / xh[k].norm_sqr();
+            out.push(Point {
+                bin: k as f32 - (xdh[k] * inv).im * binwidth,
+                time: (xth[k] * inv).re,
+                power,
+            });
+        }
+    }
+}
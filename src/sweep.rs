@@ -0,0 +1,262 @@
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+
+// Impulse response measurement by exponential sine sweep (Farina): the
+// response to the sweep is convolved with an amplitude-compensated,
+// time-reversed copy of it, which leaves the linear IR at time zero and any
+// harmonic distortion products before it.
+
+const FADE: f64 = 0.01;
+const TRIGGER: f32 = 0.01;
+
+pub struct Sweep {
+    pub rate: f64,
+    pub signal: Vec<f32>,
+    pub inverse: Vec<f32>,
+}
+
+impl Sweep {
+    /// A sweep from `f1` to `f2` Hz over `secs` seconds; an error if that is
+    /// too short (or the range too narrow) to measure anything with.
+    pub fn new(rate: f64, secs: f64, f1: f64, f2: f64, level: f64) -> Result<Sweep, String> {
+        if !(f1 > 0.0 && f2 > f1) {
+            return Err(format!("bad sweep range {} to {} Hz", f1, f2));
+        }
+        let len = (rate * secs).max(0.0) as usize;
+        if len == 0 {
+            return Err(format!("sweep of {} s is empty", secs));
+        }
+        let r = (f2 / f1).ln();
+        let fade = (FADE * rate) as usize;
+        let signal: Vec<f32> = (0 .. len)
+            .map(|n| {
+                let t = n as f64 / rate;
+                let env = if n < fade {
+                    0.5 - 0.5 * (PI * n as f64 / fade as f64).cos()
+                } else if n + fade > len {
+                    0.5 - 0.5 * (PI * (len - n) as f64 / fade as f64).cos()
+                } else {
+                    1.0
+                };
+                (level * env * (2.0 * PI * f1 * secs / r * ((t * r / secs).exp() - 1.0)).sin()) as f32
+            })
+            .collect();
+        // Reversed, and falling 6dB/octave to undo the sweep's pink spectrum
+        let mut inverse: Vec<f32> = signal.iter().rev().enumerate()
+            .map(|(n, &s)| s * (-(n as f64) / rate * r / secs).exp() as f32)
+            .collect();
+
+        // Normalize so the deconvolved sweep has unity gain in-band
+        let size = (len * 2).next_power_of_two();
+        let sig = spectrum(&signal, size);
+        let inv = spectrum(&inverse, size);
+        let (b1, b2) = ((f1 * 2.0 / rate * size as f64) as usize, (f2 / 2.0 / rate * size as f64) as usize);
+        if b2 <= b1 {
+            return Err(format!("sweep of {} s is too short to resolve {} to {} Hz", secs, f1, f2));
+        }
+        let gain = sig[b1 .. b2].iter().zip(&inv[b1 .. b2])
+            .map(|(s, i)| (s * i).norm() as f64)
+            .sum::<f64>() / (b2 - b1) as f64;
+        if !(gain.is_finite() && gain > 0.0) {
+            return Err(format!("sweep of {} s has no energy in band", secs));
+        }
+        for s in inverse.iter_mut() {
+            *s /= gain as f32;
+        }
+
+        Ok(Sweep { rate, signal, inverse })
+    }
+
+    /// The IR in `capture`, starting at zero latency (what arrives later is
+    /// the system's latency).
+    pub fn deconvolve(&self, capture: &[f32]) -> Vec<f32> {
+        let size = (capture.len() + self.inverse.len()).next_power_of_two();
+        let mut cap = spectrum(capture, size);
+        let inv = spectrum(&self.inverse, size);
+        for (c, i) in cap.iter_mut().zip(&inv) {
+            *c *= i;
+        }
+        let mut plan = FftPlanner::new();
+        plan.plan_fft_inverse(size).process(&mut cap);
+        let start = self.inverse.len() - 1;
+        cap[start .. start + capture.len()]
+            .iter()
+            .map(|c| c.re / size as f32)
+            .collect()
+    }
+}
+
+pub fn spectrum(data: &[f32], size: usize) -> Vec<Complex<f32>> {
+    let mut buf: Vec<Complex<f32>> = data.iter().map(|&s| Complex { re: s, im: 0.0 }).collect();
+    buf.resize(size, Complex { re: 0.0, im: 0.0 });
+    let mut plan = FftPlanner::new();
+    plan.plan_fft_forward(size).process(&mut buf);
+    buf
+}
+
+pub fn write_wav<P: AsRef<Path>>(path: P, rate: f64, data: &[f32]) -> Result<(), hound::Error> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: rate as u32,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for &s in data {
+        writer.write_sample(s)?;
+    }
+    writer.finalize()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Idle,
+    /// Waiting for an externally played sweep to arrive
+    Armed,
+    Measuring,
+    Done,
+}
+
+/// Shared between the audio callbacks (which play and record) and the view
+/// (which starts a measurement and collects the capture).
+pub struct Session {
+    pub sweep: Arc<Sweep>,
+    pub phase: Phase,
+    pub external: bool,
+    capture_len: usize,
+    capture: Vec<f32>,
+    play: Option<usize>,
+}
+
+impl Session {
+    pub fn new(sweep: Sweep, tail: f64, external: bool) -> Session {
+        let capture_len = sweep.signal.len() + (tail * sweep.rate) as usize;
+        Session {
+            sweep: Arc::new(sweep),
+            phase: Phase::Idle,
+            external,
+            capture_len,
+            capture: Vec::with_capacity(capture_len),
+            play: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.capture.clear();
+        if self.external {
+            self.phase = Phase::Armed;
+        } else {
+            self.phase = Phase::Measuring;
+            self.play = Some(0);
+        }
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.capture.len() as f32 / self.capture_len as f32
+    }
+
+    pub fn record(&mut self, samples: &[f32]) {
+        let samples = match self.phase {
+            Phase::Armed => match samples.iter().position(|s| s.abs() > TRIGGER) {
+                Some(idx) => {
+                    self.phase = Phase::Measuring;
+                    &samples[idx ..]
+                },
+                None => return,
+            },
+            Phase::Measuring => samples,
+            _ => return,
+        };
+        let take = std::cmp::min(samples.len(), self.capture_len - self.capture.len());
+        self.capture.extend_from_slice(&samples[.. take]);
+        if self.capture.len() >= self.capture_len {
+            self.phase = Phase::Done;
+        }
+    }
+
+    /// Fill an interleaved output buffer with the sweep (on every channel)
+    /// while one is playing, silence otherwise.
+    pub fn play(&mut self, out: &mut [f32], channels: usize) {
+        for frame in out.chunks_mut(channels) {
+            let samp = match self.play {
+                Some(pos) if pos < self.sweep.signal.len() => {
+                    self.play = Some(pos + 1);
+                    self.sweep.signal[pos]
+                },
+                _ => {
+                    self.play = None;
+                    0.0
+                },
+            };
+            frame.fill(samp);
+        }
+    }
+
+    /// Once a measurement is done, take its capture (and go idle).
+    pub fn take_capture(&mut self) -> Option<Vec<f32>> {
+        if self.phase != Phase::Done {
+            return None;
+        }
+        self.phase = Phase::Idle;
+        Some(std::mem::take(&mut self.capture))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep() -> Sweep {
+        Sweep::new(48000.0, 1.0, 20.0, 20000.0, 0.5).unwrap()
+    }
+
+    fn peak(ir: &[f32]) -> (usize, f32) {
+        ir.iter().cloned().enumerate().max_by(|a, b| a.1.abs().total_cmp(&b.1.abs())).unwrap()
+    }
+
+    #[test]
+    fn deconvolves_a_delayed_sweep_to_an_impulse() {
+        let sweep = sweep();
+        let mut capture = vec![0.0; 100];
+        capture.extend(sweep.signal.iter().map(|s| s * 0.5));
+        capture.resize(capture.len() + 4800, 0.0);
+        let ir = sweep.deconvolve(&capture);
+        let (at, height) = peak(&ir);
+        assert_eq!(at, 100);
+        // Little left away from the impulse
+        let rest = ir[200 ..].iter().fold(0f32, |m, s| m.max(s.abs()));
+        assert!(rest < 0.05 * height.abs(), "{}", rest);
+        // The capture's gain, in band
+        let size = ir.len().next_power_of_two();
+        let gain = spectrum(&ir, size)[1000 * size / 48000].norm();
+        assert!((gain - 0.5).abs() < 0.05, "{}", gain);
+    }
+
+    #[test]
+    fn rejects_sweeps_too_short_for_their_range() {
+        assert!(Sweep::new(48000.0, 0.0, 20.0, 20000.0, 0.5).is_err());
+        assert!(Sweep::new(48000.0, 1.0, 1000.0, 500.0, 0.5).is_err());
+        assert!(Sweep::new(48000.0, 0.001, 1000.0, 1500.0, 0.5).is_err());
+    }
+
+    #[test]
+    fn session_captures_after_the_sweep_arrives() {
+        let sweep = sweep();
+        let len = sweep.signal.len();
+        let mut session = Session::new(sweep, 0.1, true);
+        session.start();
+        assert_eq!(session.phase, Phase::Armed);
+        session.record(&[0.0; 64]);
+        assert_eq!(session.phase, Phase::Armed);
+        session.record(&vec![0.5; len + 4800]);
+        assert_eq!(session.phase, Phase::Done);
+        let capture = session.take_capture().unwrap();
+        assert_eq!(capture.len(), len + 4800);
+        assert_eq!(session.phase, Phase::Idle);
+        assert!(session.take_capture().is_none());
+    }
+}
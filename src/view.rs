@@ -1,12 +1,54 @@
 pub mod scope;
 pub mod spec;
 pub mod vec;
+pub mod loud;
+pub mod xfer;
+pub mod delay;
+pub mod ir;
+pub mod rta;
+pub mod chroma;
+pub mod phosphor;
+pub mod pane;
+pub mod text;
+pub mod texture;
+
+use crate::cqt::Cqt;
+use crate::delay::Estimate;
+use crate::peaks::Peak;
+use crate::reassign::Point;
+use crate::window::Window;
+use pane::Pane;
 
 use rustfft::num_complex::Complex;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+
+/// Trace and clipping colors, assigned to channels in order.
+pub const PALETTE: [(Color, Color); 6] = [
+    (Color::RGB(0, 255, 0), Color::RGB(255, 0, 0)),
+    (Color::RGB(0, 0, 255), Color::RGB(255, 0, 255)),
+    (Color::RGB(255, 0, 0), Color::RGB(255, 255, 0)),
+    (Color::RGB(255, 255, 0), Color::RGB(255, 255, 255)),
+    (Color::RGB(255, 0, 255), Color::RGB(255, 255, 255)),
+    (Color::RGB(0, 255, 255), Color::RGB(255, 255, 255)),
+];
+
+pub fn channel_color(idx: usize) -> Color { PALETTE[idx % PALETTE.len()].0 }
+pub fn clip_color(idx: usize) -> Color { PALETTE[idx % PALETTE.len()].1 }
 
 pub struct ChannelInfo<'i> {
+    pub name: &'i str,
     pub samples: &'i [f32],
     pub spectrum: &'i [Complex<f32>],
+    /// Constant-Q power spectrum, at the frequencies of `Info::cqt` (empty
+    /// unless that engine is enabled)
+    pub cq: &'i [f32],
+    /// Reassigned bins of `spectrum` (empty unless reassignment is enabled)
+    pub reassigned: &'i [Point],
+    /// Strongest spectral peaks, strongest first (empty unless enabled)
+    pub peaks: &'i [Peak],
+    /// Offset from dBFS (a full-scale sine is 0dB) to dB SPL, if calibrated
+    pub spl: Option<f32>,
 }
 
 pub struct SDLInfo<'s> {
@@ -15,12 +57,21 @@ pub struct SDLInfo<'s> {
 }
 
 pub struct Info<'i, 's> {
-    pub left: ChannelInfo<'i>,
-    pub right: ChannelInfo<'i>,
+    pub channels: Vec<ChannelInfo<'i>>,
+    pub rate: f32,
+    /// Frames received so far, up to the newest of `ChannelInfo::samples`
+    pub received: u64,
+    pub window: &'i dyn Window,
+    pub delay: Option<&'i Estimate>,
+    pub cqt: Option<&'i Cqt>,
     pub sdl: SDLInfo<'s>
 }
 
 pub trait View {
     fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>);
     fn requested_window(&self) -> usize { 0 }
+    fn pane(&self) -> &Pane;
+    fn pane_mut(&mut self) -> &mut Pane;
+    fn window_id(&self) -> u32 { self.pane().window_id() }
+    fn key(&mut self, _key: Keycode) {}
 }
@@ -0,0 +1,201 @@
+use super::{Info, View};
+use super::pane::Pane;
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+pub const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+const MIN_FREQ: f32 = 55.0;
+const MAX_FREQ: f32 = 5000.0;
+const HISTORY: usize = 256;
+const BAR_SIZE: f32 = 0.3;
+/// Below this (relative to full scale) a frame is silence and changes nothing
+const SILENCE: f32 = 1e-10;
+
+// Krumhansl-Kessler key profiles, from the tonic up
+const MAJOR: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+/// Pitch class of `freq` (0 is C), in equal temperament from A440.
+pub fn pitch_class(freq: f32) -> usize {
+    ((12.0 * (freq / 440.0).log2()).round() as i32 + 9).rem_euclid(12) as usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub tonic: usize,
+    pub minor: bool,
+}
+
+/// The key whose profile best correlates with `chroma`, and that correlation;
+/// None if `chroma` is flat (as when there has been nothing to hear).
+pub fn estimate_key(chroma: &[f32; 12]) -> Option<(Key, f32)> {
+    let mc = chroma.iter().sum::<f32>() / 12.0;
+    let dc: f32 = chroma.iter().map(|c| (c - mc).powi(2)).sum();
+    if dc.is_nan() || dc <= f32::EPSILON {
+        return None;
+    }
+    let corr = |profile: &[f32; 12], tonic: usize| {
+        let mp = profile.iter().sum::<f32>() / 12.0;
+        let (mut num, mut dp) = (0f32, 0f32);
+        for (pc, &c) in chroma.iter().enumerate() {
+            let p = profile[(pc + 12 - tonic) % 12];
+            num += (c - mc) * (p - mp);
+            dp += (p - mp).powi(2);
+        }
+        num / (dc * dp).sqrt()
+    };
+    (0 .. 12)
+        .flat_map(|tonic| [(Key { tonic, minor: false }, corr(&MAJOR, tonic)), (Key { tonic, minor: true }, corr(&MINOR, tonic))])
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+// Around the circle of fifths, so related keys get similar colors
+fn class_color(pc: usize, level: f32) -> Color {
+    let hue = ((pc * 7) % 12) as f32 / 2.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    let level = level.clamp(0.0, 1.0) * 255.0;
+    Color::RGB((r * level) as u8, (g * level) as u8, (b * level) as u8)
+}
+
+/// Chromagram: spectral energy folded into the 12 pitch classes, as bars and
+/// a scrolling history, with the key estimated from a longer average.
+pub struct Chroma {
+    pub view: Pane,
+    /// Time constant of the average behind the key estimate, in seconds
+    pub key_time: f32,
+    pub history: VecDeque<[f32; 12]>,
+    pub average: [f32; 12],
+    pub last: Option<Instant>,
+    pub title: String,
+}
+
+impl Chroma {
+    /// Summed over channels, from the constant-Q spectrum when there is one
+    /// and the FFT otherwise; normalized so the strongest class is 1.
+    fn chroma(info: &Info) -> [f32; 12] {
+        let mut out = [0f32; 12];
+        let mut add = |freq: f32, power: f32| {
+            if (MIN_FREQ .. MAX_FREQ).contains(&freq) {
+                out[pitch_class(freq)] += power;
+            }
+        };
+        for ci in &info.channels {
+            match info.cqt {
+                Some(cqt) if !ci.cq.is_empty() => {
+                    for (&f, &p) in cqt.freqs().iter().zip(ci.cq) {
+                        add(f, p);
+                    }
+                },
+                _ => {
+                    let n = ci.spectrum.len();
+                    let scale = info.window.power_scale();
+                    for k in 1 .. n / 2 {
+                        add(k as f32 * info.rate / n as f32, ci.spectrum[k].norm_sqr() * scale);
+                    }
+                },
+            }
+        }
+        let max = out.iter().cloned().fold(0f32, f32::max);
+        if max > SILENCE {
+            for c in out.iter_mut() {
+                *c /= max;
+            }
+        } else {
+            out = [0.0; 12];
+        }
+        out
+    }
+}
+
+impl View for Chroma {
+    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
+        let _g = hprof::enter("Chroma::render");
+        let (width, height) = self.view.output_size().expect("getting size");
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.clear();
+
+        let chroma = Chroma::chroma(info);
+        let now = Instant::now();
+        if chroma.iter().any(|&c| c > 0.0) {
+            let alpha = match self.last {
+                Some(last) if self.key_time > 0.0 => 1.0 - (-(now - last).as_secs_f32() / self.key_time).exp(),
+                _ => 1.0,
+            };
+            for (avg, &c) in self.average.iter_mut().zip(&chroma) {
+                *avg += alpha * (c - *avg);
+            }
+        }
+        self.last = Some(now);
+        self.history.push_front(chroma);
+        self.history.truncate(HISTORY);
+        let estimate = estimate_key(&self.average);
+
+        let col = width as f32 / 12.0;
+        let bar_height = (BAR_SIZE * height as f32) as u32;
+        for (pc, &c) in chroma.iter().enumerate() {
+            let x = (pc as f32 * col) as i32;
+            let w = std::cmp::max(col as u32, 2) - 1;
+            let h = (c * bar_height as f32) as u32;
+            self.view.set_draw_color(class_color(pc, 1.0));
+            self.view.fill_rect(Rect::new(x, (bar_height - h) as i32, w, h)).expect("drawing");
+            if estimate.is_some_and(|(key, _)| key.tonic == pc) {
+                self.view.set_draw_color(Color::RGB(255, 255, 255));
+                self.view.draw_rect(Rect::new(x, 0, w, bar_height)).expect("drawing");
+            }
+        }
+
+        // Newest at the top
+        let row = (height - bar_height) as f32 / HISTORY as f32;
+        for (age, frame) in self.history.iter().enumerate() {
+            let y = bar_height as i32 + (age as f32 * row) as i32;
+            let h = std::cmp::max(((age + 1) as f32 * row) as i32 - (age as f32 * row) as i32, 1) as u32;
+            for (pc, &c) in frame.iter().enumerate() {
+                if c <= 0.0 {
+                    continue;
+                }
+                self.view.set_draw_color(class_color(pc, c));
+                self.view.fill_rect(Rect::new((pc as f32 * col) as i32, y, col.ceil() as u32, h)).expect("drawing");
+            }
+        }
+
+        let title = match estimate {
+            Some((key, corr)) => format!(
+                "chroma: {} {} (r={:.2}; R resets)",
+                NAMES[key.tonic], if key.minor { "minor" } else { "major" }, corr,
+            ),
+            None => "chroma: no key yet (R resets)".to_string(),
+        };
+        if title != self.title {
+            self.view.set_title(&title).expect("setting title");
+            self.title = title;
+        }
+
+        drop(_g);
+
+        self.view.present();
+    }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+
+    fn key(&mut self, key: Keycode) {
+        if key == Keycode::R {
+            self.average = [0.0; 12];
+            self.last = None;
+        }
+    }
+}
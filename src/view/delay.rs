@@ -0,0 +1,76 @@
+use super::{Info, View};
+use super::pane::Pane;
+use crate::delay::SPEED_OF_SOUND;
+
+use sdl2::pixels::Color;
+use sdl2::render::BlendMode;
+
+pub struct Delay {
+    pub view: Pane,
+    /// Displayed lag either side of zero, in ms
+    pub range: f32,
+    pub title: String,
+}
+
+impl View for Delay {
+    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
+        let _g = hprof::enter("Delay::render");
+        let (width, height) = self.view.output_size().expect("getting size");
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.clear();
+
+        let est = match info.delay {
+            Some(e) => e,
+            None => {
+                self.view.present();
+                return;
+            },
+        };
+
+        let center = est.max_lag() as f32;
+        let span = (self.range / 1000.0 * info.rate).min(center).max(1.0);
+        let lag_x = |lag: f32| ((lag + span) / (2.0 * span) * width as f32) as i32;
+        let mid = height as i32 / 2;
+
+        self.view.set_draw_color(Color::RGB(63, 63, 63));
+        self.view.draw_line((0, mid), (width as i32, mid)).expect("drawing");
+        self.view.draw_line((lag_x(0.0), 0), (lag_x(0.0), height as i32)).expect("drawing");
+
+        self.view.set_blend_mode(BlendMode::Add);
+        self.view.set_draw_color(Color::RGB(0, 255, 0));
+        let first = (center - span).floor() as usize;
+        let last = std::cmp::min((center + span).ceil() as usize, est.correlation.len() - 1);
+        let mut lastpt = None;
+        for (idx, &c) in est.correlation.iter().enumerate().take(last + 1).skip(first) {
+            let pt = (lag_x(idx as f32 - center), mid - (c.clamp(-1.0, 1.0) * mid as f32) as i32);
+            if let Some(lp) = lastpt {
+                self.view.draw_line(lp, pt).expect("drawing");
+            }
+            lastpt = Some(pt);
+        }
+
+        self.view.set_draw_color(Color::RGB(255, 0, 0));
+        let px = lag_x(est.lag);
+        self.view.draw_line((px, 0), (px, height as i32)).expect("drawing");
+
+        let secs = est.lag / info.rate;
+        let title = format!(
+            "delay ({}->{}): {:.1} samples, {:.3} ms, {:.3} m (peak {:.2})",
+            info.channels[est.reference].name, info.channels[est.measure].name,
+            est.lag, secs * 1000.0, secs * SPEED_OF_SOUND, est.peak,
+        );
+        if title != self.title {
+            self.view.set_title(&title).expect("setting title");
+            self.title = title;
+        }
+
+        drop(_g);
+
+        self.view.set_blend_mode(BlendMode::None);
+        self.view.present();
+    }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+}
@@ -0,0 +1,212 @@
+use super::{Info, View};
+use super::pane::Pane;
+use crate::sweep::{self, Phase, Session, Sweep};
+
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+const PRE_DELAY: f32 = 0.002;
+const MIN_FREQ: f32 = 20.0;
+const RESPONSE_RANGE: f32 = 60.0;
+const ETC_RANGE: f32 = 90.0;
+
+/// The IR, frequency response and energy-time curve (ETC) of a sweep.
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub ir: Vec<f32>,
+    pub latency: usize,
+    pub response: Vec<f32>,
+    pub etc: Vec<f32>,
+}
+
+impl Analysis {
+    /// Deconvolve `capture`. An IR too short to have a spectrum comes out
+    /// empty.
+    pub fn new(sweep: &Sweep, capture: &[f32], rate: f32) -> Analysis {
+        let full = sweep.deconvolve(capture);
+        let peak = full.iter().enumerate()
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+            .map_or(0, |(idx, _)| idx);
+        let start = peak.saturating_sub((PRE_DELAY * rate) as usize);
+        let len = capture.len().saturating_sub(sweep.signal.len());
+        let ir = full.get(start .. std::cmp::min(start + len, full.len())).unwrap_or_default().to_vec();
+        if ir.len() < 2 {
+            return Analysis::default();
+        }
+
+        let size = ir.len().next_power_of_two();
+        let spec = sweep::spectrum(&ir, size);
+        let response = spec[.. size / 2].iter()
+            .map(|c| 20.0 * c.norm().max(1e-10).log10())
+            .collect();
+
+        // ETC is the envelope of the analytic signal
+        let mut analytic = spec;
+        for (bin, c) in analytic.iter_mut().enumerate() {
+            if bin > 0 && bin < size / 2 {
+                *c *= 2.0;
+            } else if bin > size / 2 {
+                *c = Complex { re: 0.0, im: 0.0 };
+            }
+        }
+        FftPlanner::new().plan_fft_inverse(size).process(&mut analytic);
+        let env: Vec<f32> = analytic[.. ir.len()].iter().map(|c| c.norm() / size as f32).collect();
+        let max = env.iter().cloned().fold(f32::EPSILON, f32::max);
+        let etc = env.iter().map(|e| 20.0 * (e / max).max(1e-10).log10()).collect();
+        Analysis { ir, latency: start, response, etc }
+    }
+}
+
+/// Impulse response measurement: the analysis of the last sweep, which is
+/// done on a thread of its own once the capture is in.
+pub struct Impulse {
+    pub view: Pane,
+    pub session: Arc<Mutex<Session>>,
+    pub out_path: String,
+    pub analysis: Analysis,
+    pub analyzing: Option<JoinHandle<Analysis>>,
+    pub title: String,
+}
+
+impl Impulse {
+    fn save(&self) {
+        let ir = &self.analysis.ir;
+        if ir.is_empty() {
+            return;
+        }
+        let rate = self.session.lock().unwrap().sweep.rate;
+        match sweep::write_wav(&self.out_path, rate, ir) {
+            Ok(()) => println!("wrote IR to {}", self.out_path),
+            Err(e) => eprintln!("writing IR to {}: {}", self.out_path, e),
+        }
+    }
+
+    // Per-column (min, max) over a time series, for a width-pixel plot
+    fn columns(data: &[f32], width: u32) -> Vec<(f32, f32)> {
+        (0 .. width as usize)
+            .map(|x| {
+                let a = x * data.len() / width as usize;
+                let b = std::cmp::max((x + 1) * data.len() / width as usize, a + 1);
+                data[a .. std::cmp::min(b, data.len())].iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)))
+            })
+            .collect()
+    }
+}
+
+impl View for Impulse {
+    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
+        let _g = hprof::enter("Impulse::render");
+        let (width, height) = self.view.output_size().expect("getting size");
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.clear();
+
+        if self.analyzing.as_ref().is_some_and(|h| h.is_finished()) {
+            self.analysis = self.analyzing.take().unwrap().join().expect("analyzing IR");
+        }
+        // A capture that comes in during an analysis waits in the session
+        // until it's done
+        let (capture, phase, progress, sweep) = {
+            let mut sess = self.session.lock().unwrap();
+            let capture = if self.analyzing.is_none() { sess.take_capture() } else { None };
+            (capture, sess.phase, sess.progress(), sess.sweep.clone())
+        };
+        if let Some(cap) = capture {
+            // Off the session (and so the audio callbacks) and the render thread
+            let rate = info.rate;
+            self.analyzing = Some(thread::spawn(move || Analysis::new(&sweep, &cap, rate)));
+        }
+        let an = &self.analysis;
+
+        let pane = height / 3;
+        self.view.set_draw_color(Color::RGB(63, 63, 63));
+        for y in [pane, pane * 2] {
+            self.view.draw_line((0, y as i32), (width as i32, y as i32)).expect("drawing");
+        }
+        if phase == Phase::Measuring {
+            self.view.set_draw_color(Color::RGB(127, 0, 0));
+            self.view.fill_rect(Rect::new(0, 0, (progress * width as f32) as u32, 4)).expect("drawing");
+        }
+
+        self.view.set_blend_mode(BlendMode::Add);
+        if !an.ir.is_empty() {
+            // IR, normalized to its peak
+            let cols = Impulse::columns(&an.ir, width);
+            let peak = an.ir.iter().fold(f32::EPSILON, |m, s| m.max(s.abs()));
+            let mid = pane as f32 / 2.0;
+            self.view.set_draw_color(Color::RGB(0, 255, 0));
+            for (x, &(lo, hi)) in cols.iter().enumerate() {
+                if !lo.is_finite() { continue; }
+                self.view.draw_line(
+                    (x as i32, (mid - hi / peak * mid) as i32),
+                    (x as i32, (mid - lo / peak * mid) as i32),
+                ).expect("drawing");
+            }
+
+            // Frequency response on a log axis, top at the next 6dB above the maximum
+            let nyquist = info.rate / 2.0;
+            let bins = an.response.len();
+            let top = (an.response.iter().cloned().fold(f32::NEG_INFINITY, f32::max) / 6.0).ceil() * 6.0;
+            self.view.set_draw_color(Color::RGB(255, 255, 0));
+            let mut last = None;
+            for x in 0 .. width {
+                let f = MIN_FREQ * (nyquist / MIN_FREQ).powf(x as f32 / width as f32);
+                let bin = std::cmp::min((f / nyquist * bins as f32) as usize, bins - 1);
+                let y = pane as i32 + ((top - an.response[bin]) / RESPONSE_RANGE * pane as f32).min(pane as f32) as i32;
+                if let Some(l) = last {
+                    self.view.draw_line(l, (x as i32, y)).expect("drawing");
+                }
+                last = Some((x as i32, y));
+            }
+
+            // ETC, 0dB at the top
+            let cols = Impulse::columns(&an.etc, width);
+            self.view.set_draw_color(Color::RGB(0, 191, 255));
+            for (x, &(_, hi)) in cols.iter().enumerate() {
+                let y = 2 * pane as i32 + (-hi / ETC_RANGE * pane as f32).min(pane as f32) as i32;
+                self.view.draw_line((x as i32, y), (x as i32, 3 * pane as i32)).expect("drawing");
+            }
+        }
+
+        let title = match phase {
+            _ if self.analyzing.is_some() => "ir: analyzing".to_string(),
+            Phase::Idle if an.ir.is_empty() => "ir: M to measure".to_string(),
+            Phase::Armed => "ir: waiting for sweep".to_string(),
+            Phase::Measuring => format!("ir: measuring ({:.0}%)", progress * 100.0),
+            _ => format!(
+                "ir: {:.0} ms, latency {:.1} ms (M to measure, W to write {})",
+                an.ir.len() as f32 / info.rate * 1000.0,
+                an.latency as f32 / info.rate * 1000.0,
+                self.out_path,
+            ),
+        };
+        if title != self.title {
+            self.view.set_title(&title).expect("setting title");
+            self.title = title;
+        }
+
+        drop(_g);
+
+        self.view.set_blend_mode(BlendMode::None);
+        self.view.present();
+    }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+
+    fn key(&mut self, key: Keycode) {
+        match key {
+            Keycode::M => self.session.lock().unwrap().start(),
+            Keycode::W => self.save(),
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use super::{Info, View};
+use super::pane::Pane;
+use crate::loudness::Meter;
+use crate::weighting::Weighting;
+
+use std::sync::{Arc, Mutex};
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+pub const TARGETS: [f32; 2] = [-23.0, -14.0];
+
+pub struct Loudness {
+    pub view: Pane,
+    pub meter: Arc<Mutex<Meter>>,
+    pub target: f32,
+    pub range: f32,
+    /// For the per-channel level readout (in dB SPL where calibrated), which
+    /// is of the spectrum rather than K-weighted like the loudness
+    pub weighting: Weighting,
+    /// Power gain per bin, from `weighting`
+    pub weights: Vec<f32>,
+    pub title: String,
+}
+
+impl Loudness {
+    fn level_y(&self, lufs: f32, height: u32) -> i32 {
+        let top = self.target + self.range / 4.0;
+        let y = (top - lufs) / self.range * height as f32;
+        y.max(0.0).min(height as f32) as i32
+    }
+}
+
+impl View for Loudness {
+    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
+        let _g = hprof::enter("Loudness::render");
+        let (width, height) = self.view.output_size().expect("getting size");
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.clear();
+
+        let (momentary, short_term, integrated, range, history) = {
+            let meter = self.meter.lock().unwrap();
+            let hist: Vec<_> = meter.history().iter().rev().take(width as usize).cloned().collect();
+            (meter.momentary(), meter.short_term(), meter.integrated(), meter.range(), hist)
+        };
+
+        // Bars on the left quarter: momentary, short-term, integrated
+        let bar_w = width / 12;
+        let bars_w = bar_w * 3;
+        let bars = [
+            (momentary, Color::RGB(0, 255, 0)),
+            (short_term, Color::RGB(0, 255, 255)),
+            (integrated, Color::RGB(255, 255, 255)),
+        ];
+        for (idx, &(level, color)) in bars.iter().enumerate() {
+            if !level.is_finite() { continue; }
+            let y = self.level_y(level, height);
+            let over = level > self.target + 1.0;
+            self.view.set_draw_color(if over { Color::RGB(255, 0, 0) } else { color });
+            self.view.fill_rect(Rect::new(
+                (idx as u32 * bar_w) as i32 + 1, y,
+                bar_w.saturating_sub(2), height - y as u32,
+            )).expect("drawing");
+        }
+
+        self.view.set_blend_mode(BlendMode::Add);
+        if let Some((lo, hi)) = range {
+            let (ylo, yhi) = (self.level_y(lo, height), self.level_y(hi, height));
+            self.view.set_draw_color(Color::RGB(48, 48, 0));
+            self.view.fill_rect(Rect::new(
+                bars_w as i32, yhi, width - bars_w, (ylo - yhi) as u32,
+            )).expect("drawing");
+        }
+
+        // Grid every 6 LU about the target, and the target itself
+        self.view.set_draw_color(Color::RGB(32, 32, 32));
+        let mut lu = self.target - 6.0 * (self.range / 6.0).ceil();
+        while lu < self.target + self.range {
+            let y = self.level_y(lu, height);
+            self.view.draw_line((0, y), (width as i32, y)).expect("drawing");
+            lu += 6.0;
+        }
+        self.view.set_draw_color(Color::RGB(127, 0, 0));
+        let ty = self.level_y(self.target, height);
+        self.view.draw_line((0, ty), (width as i32, ty)).expect("drawing");
+
+        // History, newest at the right edge
+        for (trace, color) in [(false, Color::RGB(0, 127, 0)), (true, Color::RGB(0, 255, 255))] {
+            self.view.set_draw_color(color);
+            let mut last: Option<(i32, i32)> = None;
+            for (age, pt) in history.iter().enumerate() {
+                let level = if trace { pt.short_term } else { pt.momentary };
+                let x = width as i32 - 1 - age as i32;
+                if x < bars_w as i32 { break; }
+                if !level.is_finite() {
+                    last = None;
+                    continue;
+                }
+                let y = self.level_y(level, height);
+                if let Some(l) = last {
+                    self.view.draw_line(l, (x, y)).expect("drawing");
+                }
+                last = Some((x, y));
+            }
+        }
+
+        let bins = info.channels[0].spectrum.len();
+        if self.weights.len() != bins / 2 {
+            self.weights = self.weighting.table(bins, info.rate).iter()
+                .map(|db| 10f32.powf(db / 10.0))
+                .collect();
+        }
+        let scale = info.window.power_scale();
+        let levels: Vec<String> = info.channels.iter().map(|ci| {
+            let total: f32 = (1 .. self.weights.len())
+                .map(|k| ci.spectrum[k].norm_sqr() * scale * self.weights[k])
+                .sum();
+            let level = 10.0 * (2.0 * total).max(1e-20).log10();
+            match ci.spl {
+                Some(offset) => format!("{} {:.1} dB SPL({})", ci.name, level + offset, self.weighting.curve.name()),
+                None => format!("{} {:.1} dBFS", ci.name, level),
+            }
+        }).collect();
+
+        let title = format!(
+            "loud: M {:.1} S {:.1} I {:.1} LUFS, LRA {:.1} LU (target {:.0}); {}: {}",
+            momentary, short_term, integrated,
+            range.map_or(0.0, |(lo, hi)| hi - lo),
+            self.target,
+            self.weighting.name(), levels.join(", "),
+        );
+        if title != self.title {
+            self.view.set_title(&title).expect("setting title");
+            self.title = title;
+        }
+
+        drop(_g);
+
+        self.view.set_blend_mode(BlendMode::None);
+        self.view.present();
+    }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+
+    fn key(&mut self, key: Keycode) {
+        match key {
+            Keycode::R => self.meter.lock().unwrap().reset(),
+            Keycode::Num1 => self.target = TARGETS[0],
+            Keycode::Num2 => self.target = TARGETS[1],
+            Keycode::W => {
+                self.weighting.curve = self.weighting.curve.next();
+                self.weights.clear();
+            },
+            _ => (),
+        }
+    }
+}
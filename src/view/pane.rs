@@ -0,0 +1,152 @@
+use std::cell::RefCell;
+use std::ffi::NulError;
+use std::rc::Rc;
+
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+use crate::placement::Placement;
+
+/// A view's place in a window shared with others (see `layout::Tiles`).
+pub struct Tile {
+    /// Stands in for a window id when routing events to the view
+    pub id: u32,
+    pub area: Rect,
+    pub title: String,
+}
+
+enum Target {
+    /// With the window's name, and where it is when not fullscreen
+    Own(Canvas<Window>, String, Placement),
+    Tile(Rc<RefCell<Canvas<Window>>>, Rc<RefCell<Tile>>),
+}
+
+/// Where a view draws: a window of its own, or a tile of a shared one. It
+/// offers the subset of `Canvas` the views use, in the tile's coordinates.
+pub struct Pane {
+    target: Target,
+}
+
+impl Pane {
+    pub fn new(canvas: Canvas<Window>, name: &str, windowed: Placement) -> Pane {
+        Pane { target: Target::Own(canvas, name.into(), windowed) }
+    }
+
+    pub fn tile(canvas: Rc<RefCell<Canvas<Window>>>, tile: Rc<RefCell<Tile>>) -> Pane {
+        Pane { target: Target::Tile(canvas, tile) }
+    }
+
+    /// Run `f` on the canvas, with a tile's viewport set.
+    fn with<R>(&mut self, f: impl FnOnce(&mut Canvas<Window>) -> R) -> R {
+        match &mut self.target {
+            Target::Own(canvas, ..) => f(canvas),
+            Target::Tile(canvas, tile) => {
+                let mut canvas = canvas.borrow_mut();
+                canvas.set_viewport(tile.borrow().area);
+                f(&mut canvas)
+            },
+        }
+    }
+
+    pub fn output_size(&self) -> Result<(u32, u32), String> {
+        match &self.target {
+            Target::Own(canvas, ..) => canvas.output_size(),
+            Target::Tile(_, tile) => Ok(tile.borrow().area.size()),
+        }
+    }
+
+    pub fn window_id(&self) -> u32 {
+        match &self.target {
+            Target::Own(canvas, ..) => canvas.window().id(),
+            Target::Tile(_, tile) => tile.borrow().id,
+        }
+    }
+
+    pub fn set_title(&mut self, title: &str) -> Result<(), NulError> {
+        match &mut self.target {
+            Target::Own(canvas, ..) => canvas.window_mut().set_title(title),
+            Target::Tile(_, tile) => {
+                tile.borrow_mut().title = title.into();
+                Ok(())
+            },
+        }
+    }
+
+    pub fn texture_creator(&self) -> TextureCreator<WindowContext> {
+        match &self.target {
+            Target::Own(canvas, ..) => canvas.texture_creator(),
+            Target::Tile(canvas, _) => canvas.borrow().texture_creator(),
+        }
+    }
+
+    pub fn set_draw_color<C: Into<Color>>(&mut self, color: C) {
+        self.with(|c| c.set_draw_color(color))
+    }
+
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.with(|c| c.set_blend_mode(mode))
+    }
+
+    /// Fill the whole pane with the draw color (as `Canvas::clear` does for
+    /// a window, which would clear the other tiles too).
+    pub fn clear(&mut self) {
+        if let Target::Own(canvas, ..) = &mut self.target {
+            return canvas.clear();
+        }
+        self.with(|c| {
+            let mode = c.blend_mode();
+            c.set_blend_mode(BlendMode::None);
+            c.fill_rect(None).expect("clearing");
+            c.set_blend_mode(mode);
+        })
+    }
+
+    pub fn draw_line<P1: Into<Point>, P2: Into<Point>>(&mut self, start: P1, end: P2) -> Result<(), String> {
+        self.with(|c| c.draw_line(start, end))
+    }
+
+    pub fn draw_rect(&mut self, rect: Rect) -> Result<(), String> {
+        self.with(|c| c.draw_rect(rect))
+    }
+
+    pub fn fill_rect<R: Into<Option<Rect>>>(&mut self, rect: R) -> Result<(), String> {
+        self.with(|c| c.fill_rect(rect))
+    }
+
+    pub fn fill_rects(&mut self, rects: &[Rect]) -> Result<(), String> {
+        self.with(|c| c.fill_rects(rects))
+    }
+
+    pub fn copy<R1, R2>(&mut self, texture: &Texture, src: R1, dst: R2) -> Result<(), String>
+        where R1: Into<Option<Rect>>, R2: Into<Option<Rect>>
+    {
+        self.with(|c| c.copy(texture, src, dst))
+    }
+
+    /// The name of this pane's own window and where it is, to be restored
+    /// next time; None for a tile.
+    pub fn placement(&self) -> Option<(&str, Placement)> {
+        match &self.target {
+            Target::Own(canvas, name, windowed) => Some((name, Placement::of(canvas.window(), windowed))),
+            Target::Tile(..) => None,
+        }
+    }
+
+    /// Switch this pane's own window in or out of fullscreen (a tile's is
+    /// switched by `layout::Tiles`).
+    pub fn toggle_fullscreen(&mut self) {
+        if let Target::Own(canvas, _, windowed) = &mut self.target {
+            Placement::toggle_fullscreen(canvas.window_mut(), windowed);
+        }
+    }
+
+    /// Show what was drawn; for a tile, the shared window is presented once
+    /// all its tiles are drawn instead.
+    pub fn present(&mut self) {
+        if let Target::Own(canvas, ..) = &mut self.target {
+            canvas.present();
+        }
+    }
+}
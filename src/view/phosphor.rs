@@ -0,0 +1,178 @@
+use std::time::Instant;
+
+use super::pane::Pane;
+use super::texture::OwnedTexture;
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::BlendMode;
+
+/// Assumed for the first frame, before there is a last one to time from
+const FIRST_FRAME: f32 = 1.0 / 60.0;
+
+/// How a layer's accumulated intensity becomes color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMap {
+    /// The given color, whitening as it saturates
+    Tint(Color),
+    /// Black through blue, green and yellow to white
+    Heat,
+}
+
+impl ColorMap {
+    /// Color at `v` (0 to 1), as (r, g, b).
+    fn map(self, v: f32) -> (f32, f32, f32) {
+        match self {
+            ColorMap::Tint(c) => {
+                let white = (v - 0.75).max(0.0) * 4.0;
+                let tint = |comp: u8| v * (comp as f32 + (255.0 - comp as f32) * white);
+                (tint(c.r), tint(c.g), tint(c.b))
+            },
+            ColorMap::Heat => {
+                let stops = [(0.0, 0.0, 0.0), (0.0, 0.0, 255.0), (0.0, 255.0, 0.0), (255.0, 255.0, 0.0), (255.0, 255.0, 255.0)];
+                let pos = v.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+                let idx = std::cmp::min(pos as usize, stops.len() - 2);
+                let t = pos - idx as f32;
+                let (a, b) = (stops[idx], stops[idx + 1]);
+                (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1), a.2 + t * (b.2 - a.2))
+            },
+        }
+    }
+}
+
+/// Simulated phosphor: per-pixel beam exposure, in layers (one per trace
+/// color), that decays exponentially in wall-clock time, so the image is
+/// independent of frame rate. Traces are deposited as anti-aliased lines
+/// whose exposure is spread over their length, so slow parts of a trace
+/// glow brighter than fast ones.
+pub struct Phosphor {
+    /// Time constant of the decay, in seconds
+    pub persistence: f32,
+    /// Exposure to brightness: larger saturates sooner
+    pub gain: f32,
+    width: u32,
+    height: u32,
+    layers: usize,
+    buf: Vec<f32>,
+    pixels: Vec<u8>,
+    texture: Option<OwnedTexture>,
+    exposure: f32,
+    last: Option<Instant>,
+}
+
+impl Phosphor {
+    pub fn new(persistence: f32, gain: f32) -> Phosphor {
+        Phosphor {
+            persistence,
+            gain,
+            width: 0,
+            height: 0,
+            layers: 0,
+            buf: Vec::new(),
+            pixels: Vec::new(),
+            texture: None,
+            exposure: 0.0,
+            last: None,
+        }
+    }
+
+    /// Start a frame: decay what's there by the time since the last one
+    /// (clearing it if the size or layer count changed).
+    pub fn begin(&mut self, width: u32, height: u32, layers: usize) {
+        if (width, height, layers) != (self.width, self.height, self.layers) {
+            self.width = width;
+            self.height = height;
+            self.layers = layers;
+            self.buf = vec![0.0; layers * width as usize * height as usize];
+        }
+        let now = Instant::now();
+        let dt = self.last.map_or(FIRST_FRAME, |l| (now - l).as_secs_f32());
+        self.last = Some(now);
+        let persistence = self.persistence.max(1e-3);
+        let decay = (-dt / persistence).exp();
+        for v in self.buf.iter_mut() {
+            *v *= decay;
+        }
+        // A pixel hit once a frame settles at an exposure of 1
+        self.exposure = dt / persistence;
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.fill(0.0);
+    }
+
+    fn plot(&mut self, layer: usize, x: i32, y: i32, amount: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let idx = (layer * self.height as usize + y as usize) * self.width as usize + x as usize;
+        self.buf[idx] += amount;
+    }
+
+    pub fn point(&mut self, layer: usize, (x, y): (f32, f32)) {
+        self.plot(layer, x.round() as i32, y.round() as i32, self.exposure);
+    }
+
+    /// An anti-aliased (Wu) line, with one frame's exposure spread over it.
+    /// Only the part on the image is walked, however far off it the ends are.
+    pub fn line(&mut self, layer: usize, (x0, y0): (f32, f32), (x1, y1): (f32, f32)) {
+        if ![x0, y0, x1, y1].iter().all(|v| v.is_finite()) {
+            return;
+        }
+        let len = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt().max(1.0);
+        let amount = self.exposure / len;
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        let (mut a, mut b) = if steep { ((y0, x0), (y1, x1)) } else { ((x0, y0), (x1, y1)) };
+        if a.0 > b.0 {
+            std::mem::swap(&mut a, &mut b);
+        }
+        let dx = b.0 - a.0;
+        let grad = if dx.abs() < f32::EPSILON { 0.0 } else { (b.1 - a.1) / dx };
+        let extent = if steep { self.height } else { self.width } as i32;
+        let first = std::cmp::max(a.0.round() as i32, 0);
+        let last = std::cmp::min(b.0.round() as i32, extent - 1);
+        for major in first ..= last {
+            let minor = a.1 + grad * (major as f32 - a.0);
+            let (base, frac) = (minor.floor(), minor - minor.floor());
+            for (m, cover) in [(base as i32, 1.0 - frac), (base as i32 + 1, frac)] {
+                if steep {
+                    self.plot(layer, m, major, amount * cover);
+                } else {
+                    self.plot(layer, major, m, amount * cover);
+                }
+            }
+        }
+    }
+
+    /// Add the image, through one color map per layer, to `pane`.
+    pub fn draw(&mut self, pane: &mut Pane, maps: &[ColorMap]) {
+        let _g = hprof::enter("Phosphor::draw");
+        let size = self.width as usize * self.height as usize;
+        if size == 0 {
+            return;
+        }
+        self.pixels.clear();
+        self.pixels.resize(size * 4, 0);
+        for (layer, map) in maps.iter().enumerate().take(self.layers) {
+            for (px, &v) in self.pixels.chunks_mut(4).zip(&self.buf[layer * size .. (layer + 1) * size]) {
+                if v <= 0.0 {
+                    continue;
+                }
+                let (r, g, b) = map.map(1.0 - (-self.gain * v).exp());
+                // ARGB8888 is stored BGRA in (little-endian) memory
+                for (byte, comp) in px.iter_mut().zip([b, g, r]) {
+                    *byte = byte.saturating_add(comp as u8);
+                }
+            }
+        }
+        if self.texture.as_ref().map(|t| t.size()) != Some((self.width, self.height)) {
+            self.texture = None;
+            let mut tex = OwnedTexture::new(pane, PixelFormatEnum::ARGB8888, self.width, self.height)
+                .expect("creating phosphor texture");
+            tex.set_blend_mode(BlendMode::Add);
+            self.texture = Some(tex);
+        }
+        let tex = self.texture.as_mut().unwrap();
+        tex.update(None, &self.pixels, self.width as usize * 4).expect("uploading");
+        pane.copy(tex.texture(), None, None).expect("blitting");
+    }
+}
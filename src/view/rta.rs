@@ -0,0 +1,344 @@
+use super::{Info, View, channel_color};
+use super::pane::Pane;
+use crate::calibration::Calibration;
+use crate::weighting::Weighting;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+pub const FRACTIONS: [usize; 5] = [1, 3, 6, 12, 24];
+const MIN_FREQ: f32 = 20.0;
+const MAX_FREQ: f32 = 20000.0;
+const PEAK_HOLD: f32 = 2.0;
+const PEAK_FALL: f32 = 20.0;
+const CAL_TIME: Duration = Duration::from_secs(3);
+/// A channel is only calibrated if at least this much of its power is in the
+/// band around the calibrator's frequency
+const CAL_PURITY: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Band {
+    pub center: f32,
+    pub lo: f32,
+    pub hi: f32,
+}
+
+/// Parse a number of bands per octave, which must be at least one.
+pub fn parse_fraction(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(f) if f >= 1 => Ok(f),
+        _ => Err(format!("bad bands per octave {} (expected 1 or more, e.g. {:?})", s, FRACTIONS)),
+    }
+}
+
+/// ANSI S1.11 / IEC 61260 base-10 fractional-octave bands (1/`fraction`
+/// octave) covering the audible range, up to `nyquist`.
+pub fn bands(fraction: usize, nyquist: f32) -> Vec<Band> {
+    let g = 10f32.powf(0.3);
+    let b = fraction as f32;
+    let center = |x: i32| if fraction % 2 == 1 {
+        1000.0 * g.powf(x as f32 / b)
+    } else {
+        1000.0 * g.powf((2 * x + 1) as f32 / (2.0 * b))
+    };
+    let mut x = (b * (MIN_FREQ / 1000.0).log(g)).floor() as i32;
+    let mut out = Vec::new();
+    loop {
+        let fc = center(x);
+        let (lo, hi) = (fc * g.powf(-0.5 / b), fc * g.powf(0.5 / b));
+        if lo > MAX_FREQ.min(nyquist) || hi > nyquist {
+            break;
+        }
+        if hi >= MIN_FREQ {
+            out.push(Band { center: fc, lo, hi });
+        }
+        x += 1;
+    }
+    out
+}
+
+fn band_power(band: &Band, power: &dyn Fn(usize) -> f32, bins: usize, df: f32) -> f32 {
+    let (lo, hi) = (band.lo / df, band.hi / df);
+    let mut sum = 0f32;
+    let first = (lo + 0.5).floor().max(0.0) as usize;
+    let last = std::cmp::min((hi + 0.5).ceil() as usize, bins);
+    for k in first .. last {
+        // Bin k covers [k - 1/2, k + 1/2)
+        let overlap = (hi.min(k as f32 + 0.5) - lo.max(k as f32 - 0.5)).max(0.0);
+        sum += power(k) * overlap;
+    }
+    sum
+}
+
+/// An SPL calibration in progress: per channel, the summed power in the
+/// calibrator's band and in total, over `frames` frames.
+pub struct Calibrating {
+    pub until: Instant,
+    pub band: Vec<f32>,
+    pub total: Vec<f32>,
+    pub frames: usize,
+}
+
+/// Real-time analyzer: fractional-octave band levels (from summed FFT bins,
+/// with bins split fractionally at band edges) with peak hold.
+pub struct Rta {
+    pub view: Pane,
+    pub fraction: usize,
+    pub weighting: Weighting,
+    pub db_range: f32,
+    pub bands: Vec<Band>,
+    pub weights: Vec<f32>,
+    /// Per channel, per band: (level, peak, time of peak)
+    pub levels: Vec<Vec<(f32, f32, Instant)>>,
+    pub calibration: Arc<Mutex<Calibration>>,
+    /// Frequency (Hz) and level (dB SPL) of the acoustic calibrator
+    pub cal_freq: f32,
+    pub cal_level: f32,
+    pub calibrating: Option<Calibrating>,
+    /// The outcome of the last calibration, shown in the title until a key
+    /// is pressed
+    pub cal_result: Option<String>,
+    pub title: String,
+}
+
+impl Rta {
+    fn rebuild(&mut self, bins: usize, rate: f32) {
+        self.bands = bands(self.fraction, rate / 2.0);
+        self.weights = self.weighting.table(bins, rate).iter()
+            .map(|db| 10f32.powf(db / 10.0))
+            .collect();
+        self.levels.clear();
+    }
+
+    fn calibrate(&mut self, info: &Info, df: f32, scale: f32) {
+        let cal = match self.calibrating.as_mut() {
+            Some(c) => c,
+            None => return,
+        };
+        let bins = self.weights.len();
+        let band = Band {
+            center: self.cal_freq,
+            lo: self.cal_freq * 2f32.powf(-1.0 / 6.0),
+            hi: self.cal_freq * 2f32.powf(1.0 / 6.0),
+        };
+        cal.band.resize(info.channels.len(), 0.0);
+        cal.total.resize(info.channels.len(), 0.0);
+        for (chan, ci) in info.channels.iter().enumerate() {
+            let power = |k: usize| ci.spectrum[k].norm_sqr() * scale;
+            cal.band[chan] += band_power(&band, &power, bins, df);
+            cal.total[chan] += (1 .. bins).map(power).sum::<f32>();
+        }
+        cal.frames += 1;
+        if Instant::now() < cal.until {
+            return;
+        }
+
+        let cal = self.calibrating.take().unwrap();
+        // The calibrator's level is at the microphone, before its response
+        let response = self.weighting.correction.as_ref().map_or(0.0, |c| c.response(self.cal_freq));
+        let mut store = self.calibration.lock().unwrap();
+        let mut results = Vec::new();
+        for (chan, ci) in info.channels.iter().enumerate() {
+            if cal.band[chan] < CAL_PURITY * cal.total[chan] || cal.band[chan] <= 0.0 {
+                results.push(format!("no {} Hz tone on {}", self.cal_freq, ci.name));
+                continue;
+            }
+            let dbfs = 10.0 * (2.0 * cal.band[chan] / cal.frames as f32).log10() - response;
+            let offset = self.cal_level - dbfs;
+            results.push(format!("{} offset {:.1} dB", ci.name, offset));
+            store.set(ci.name, offset);
+        }
+        if let Err(e) = store.save() {
+            results.push(format!("not saved: {}", e));
+        }
+        self.cal_result = Some(results.join(", "));
+    }
+
+    fn level_y(&self, db: f32, top: f32, height: u32) -> i32 {
+        (((top - db) / self.db_range).clamp(0.0, 1.0) * height as f32) as i32
+    }
+}
+
+impl View for Rta {
+    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
+        let _g = hprof::enter("Rta::render");
+        let (width, height) = self.view.output_size().expect("getting size");
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.clear();
+
+        let bins = info.channels[0].spectrum.len();
+        if self.weights.len() != bins / 2 {
+            self.rebuild(bins, info.rate);
+        }
+        let df = info.rate / bins as f32;
+        let scale = info.window.power_scale();
+        let now = Instant::now();
+        if self.levels.len() != info.channels.len() {
+            self.levels = vec![vec![(f32::NEG_INFINITY, f32::NEG_INFINITY, now); self.bands.len()]; info.channels.len()];
+        }
+        self.calibrate(info, df, scale);
+        if self.bands.is_empty() {
+            let title = format!("rta: no 1/{} octave bands below {} Hz", self.fraction, info.rate / 2.0);
+            if title != self.title {
+                self.view.set_title(&title).expect("setting title");
+                self.title = title;
+            }
+            self.view.present();
+            return;
+        }
+
+        // Calibrated channels read in dB SPL; the top of the scale is the
+        // highest full scale among them, rounded up to 10dB
+        let top = info.channels.iter()
+            .filter_map(|ci| ci.spl)
+            .fold(None, |m: Option<f32>, o| Some(m.map_or(o, |m| m.max(o))))
+            .map_or(0.0, |o| (o / 10.0).ceil() * 10.0);
+
+        // Grid every 10dB and at the bands centered on 100Hz, 1kHz and 10kHz
+        self.view.set_draw_color(Color::RGB(32, 32, 32));
+        let mut db = top;
+        while db > top - self.db_range {
+            let y = self.level_y(db, top, height);
+            self.view.draw_line((0, y), (width as i32, y)).expect("drawing");
+            db -= 10.0;
+        }
+        let bw = width as f32 / self.bands.len() as f32;
+        for (idx, band) in self.bands.iter().enumerate() {
+            let decade = band.center.log10();
+            if (decade - decade.round()).abs() < 0.01 {
+                let x = ((idx as f32 + 0.5) * bw) as i32;
+                self.view.draw_line((x, 0), (x, height as i32)).expect("drawing");
+            }
+        }
+
+        self.view.set_blend_mode(BlendMode::Add);
+        let mut meters = Vec::new();
+        for (chan, ci) in info.channels.iter().enumerate() {
+            let power = |k: usize| ci.spectrum[k].norm_sqr() * scale * self.weights[k];
+            let offset = ci.spl.unwrap_or(0.0);
+            let color = channel_color(chan);
+            let mut total = 0f32;
+            for (idx, band) in self.bands.iter().enumerate() {
+                let p = band_power(band, &power, self.weights.len(), df);
+                total += p;
+                let db = 10.0 * (2.0 * p).max(1e-20).log10() + offset;
+                let (_, peak, at) = self.levels[chan][idx];
+                let peak = if db >= peak {
+                    self.levels[chan][idx].2 = now;
+                    db
+                } else {
+                    let held = (now - at).as_secs_f32() - PEAK_HOLD;
+                    if held > 0.0 { (peak - PEAK_FALL * held).max(db) } else { peak }
+                };
+                self.levels[chan][idx].0 = db;
+                self.levels[chan][idx].1 = peak;
+
+                let x0 = (idx as f32 * bw) as i32;
+                let w = std::cmp::max((bw as i32) - 1, 1) as u32;
+                let y = self.level_y(db, top, height);
+                self.view.set_draw_color(Color::RGB(color.r / 2, color.g / 2, color.b / 2));
+                self.view.fill_rect(Rect::new(x0, y, w, height - y as u32)).expect("drawing");
+                let py = self.level_y(peak, top, height);
+                self.view.set_draw_color(color);
+                self.view.draw_line((x0, py), (x0 + w as i32 - 1, py)).expect("drawing");
+            }
+            let level = 10.0 * (2.0 * total).max(1e-20).log10() + offset;
+            meters.push(match ci.spl {
+                Some(_) => format!("{} {:.1} dB SPL({})", ci.name, level, self.weighting.curve.name()),
+                None => format!("{} {:.1} dBFS", ci.name, level),
+            });
+        }
+
+        let title = match (&self.calibrating, &self.cal_result) {
+            (Some(_), _) => format!("rta: calibrating to {} dB SPL at {} Hz...", self.cal_level, self.cal_freq),
+            (None, Some(result)) => format!("rta: calibrated: {}", result),
+            (None, None) => format!("rta: 1/{} octave, {}: {}", self.fraction, self.weighting.name(), meters.join(", ")),
+        };
+        if title != self.title {
+            self.view.set_title(&title).expect("setting title");
+            self.title = title;
+        }
+
+        drop(_g);
+
+        self.view.set_blend_mode(BlendMode::None);
+        self.view.present();
+    }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+
+    fn key(&mut self, key: Keycode) {
+        self.cal_result = None;
+        match key {
+            Keycode::F => {
+                let idx = FRACTIONS.iter().position(|&f| f == self.fraction).map_or(0, |i| i + 1);
+                self.fraction = FRACTIONS[idx % FRACTIONS.len()];
+                self.weights.clear();
+            },
+            Keycode::W => {
+                self.weighting.curve = self.weighting.curve.next();
+                self.weights.clear();
+            },
+            Keycode::R => self.levels.clear(),
+            Keycode::K => self.calibrating = Some(Calibrating {
+                until: Instant::now() + CAL_TIME,
+                band: Vec::new(),
+                total: Vec::new(),
+                frames: 0,
+            }),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn octave_bands_have_the_nominal_centers() {
+        let centers: Vec<f32> = bands(1, 24000.0).iter().map(|b| b.center).collect();
+        let nominal = [16.0, 31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+        assert_eq!(centers.len(), nominal.len(), "{:?}", centers);
+        for (c, n) in centers.iter().zip(nominal) {
+            assert!((c / n - 1.0).abs() < 0.03, "{} for {}", c, n);
+        }
+    }
+
+    #[test]
+    fn bands_tile_the_audible_range() {
+        for &fraction in FRACTIONS.iter() {
+            let bands = bands(fraction, 24000.0);
+            assert!(bands[0].lo <= MIN_FREQ && bands[0].hi >= MIN_FREQ, "1/{}", fraction);
+            assert!(bands.iter().any(|b| (b.center - 1000.0).abs() < 1.0) == (fraction % 2 == 1));
+            for pair in bands.windows(2) {
+                assert!((pair[0].hi / pair[1].lo - 1.0).abs() < 1e-4, "1/{}: {:?}", fraction, pair);
+            }
+            assert!(bands.last().unwrap().hi <= 24000.0);
+        }
+        assert!(bands(3, 10.0).is_empty());
+    }
+
+    #[test]
+    fn band_power_splits_bins_at_the_edges() {
+        // A flat spectrum of 1 per bin reads the band's width in bins
+        let band = Band { center: 1000.0, lo: 707.0, hi: 1414.0 };
+        let p = band_power(&band, &|_| 1.0, 1024, 10.0);
+        assert!((p - 70.7).abs() < 1e-3, "{}", p);
+    }
+
+    #[test]
+    fn fraction_must_be_positive() {
+        assert_eq!(parse_fraction("3"), Ok(3));
+        assert!(parse_fraction("0").is_err());
+        assert!(parse_fraction("-1").is_err());
+        assert!(parse_fraction("third").is_err());
+    }
+}
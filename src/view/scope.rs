@@ -1,49 +1,404 @@
-use super::{Info, View};
+use super::{Info, View, channel_color, clip_color};
+use super::phosphor::{ColorMap, Phosphor};
+use super::pane::Pane;
+use super::text::{self, Align};
+use crate::pitch;
 
+use std::str::FromStr;
+
+use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
-use sdl2::video::Window;
-use sdl2::render::{Canvas, BlendMode};
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+const LEVEL_STEP: f32 = 0.05;
+const LABEL_COLOR: Color = Color::RGB(160, 160, 160);
+pub const DIVS_X: usize = 10;
+pub const DIVS_Y: usize = 8;
+
+/// The next value up or down the 1-2-5 sequence from `v`.
+pub fn step_125(v: f32, up: bool) -> f32 {
+    let exp = v.log10().floor();
+    let base = 10f32.powf(exp);
+    let mant = (v / base).round() as u32;
+    let steps = [1.0, 2.0, 5.0, 10.0, 20.0];
+    let idx = match mant { 0 ..= 1 => 0, 2 ..= 3 => 1, 4 ..= 7 => 2, _ => 3 };
+    if up {
+        steps[idx + 1] * base
+    } else if idx == 0 {
+        0.5 * base
+    } else {
+        steps[idx - 1] * base
+    }
+}
+
+/// Parse a vertical scale, per division: a fraction of full scale, or dBFS
+/// if suffixed with "dB". It must be positive and finite.
+pub fn parse_div(s: &str) -> Result<f32, String> {
+    let bad = |_| format!("bad vertical scale {} (expected a fraction of full scale, or dBFS with a dB suffix)", s);
+    let div = match s.strip_suffix("dB") {
+        Some(db) => 10f32.powf(db.trim().parse::<f32>().map_err(bad)? / 20.0),
+        None => s.parse().map_err(bad)?,
+    };
+    if div.is_finite() && div > 0.0 {
+        Ok(div)
+    } else {
+        Err(format!("vertical scale {} out of range (expected above zero)", s))
+    }
+}
+
+/// Parse a timebase, in milliseconds per division. It must be positive and
+/// finite.
+pub fn parse_time(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(ms) if ms.is_finite() && ms > 0.0 => Ok(ms),
+        Ok(_) => Err(format!("timebase {} out of range (expected above zero)", s)),
+        Err(_) => Err(format!("bad timebase {} (expected milliseconds per division)", s)),
+    }
+}
 
 pub fn normalize_centered(samp: f32, height: u32) -> i32 {
     let hh = height / 2;
     hh as i32 - (hh as f32 * samp) as i32
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+impl FromStr for Edge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Edge, String> {
+        match s {
+            "rising" => Ok(Edge::Rising),
+            "falling" => Ok(Edge::Falling),
+            _ => Err(format!("unknown trigger edge {} (expected rising or falling)", s)),
+        }
+    }
+}
+
+/// Auto free-runs when nothing triggers; normal holds the last triggered
+/// trace; single holds the first one after being armed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Auto,
+    Normal,
+    Single,
+}
+
+impl Mode {
+    pub fn next(self) -> Mode {
+        match self {
+            Mode::Auto => Mode::Normal,
+            Mode::Normal => Mode::Single,
+            Mode::Single => Mode::Auto,
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "auto" => Ok(Mode::Auto),
+            "normal" => Ok(Mode::Normal),
+            "single" => Ok(Mode::Single),
+            _ => Err(format!("unknown trigger mode {} (expected auto, normal or single)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Overlaid,
+    /// Each channel in its own lane, with its own scale
+    Stacked,
+    /// The first two channels against each other
+    XY,
+}
+
+impl Layout {
+    pub fn next(self) -> Layout {
+        match self {
+            Layout::Overlaid => Layout::Stacked,
+            Layout::Stacked => Layout::XY,
+            Layout::XY => Layout::Overlaid,
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Layout, String> {
+        match s {
+            "overlay" => Ok(Layout::Overlaid),
+            "stack" => Ok(Layout::Stacked),
+            "xy" => Ok(Layout::XY),
+            _ => Err(format!("unknown scope layout {} (expected overlay, stack or xy)", s)),
+        }
+    }
+}
+
+pub struct Trigger {
+    /// Channel whose edges trigger; None for the minimum-energy search
+    /// over all channels
+    pub source: Option<usize>,
+    pub edge: Edge,
+    pub level: f32,
+    /// How far past the level (the other way) the signal must go to rearm
+    pub hysteresis: f32,
+    /// Seconds after a trigger in which edges are ignored
+    pub holdoff: f32,
+    pub mode: Mode,
+    /// Single mode is waiting for its trigger
+    pub armed: bool,
+    /// Where the last trigger was in the stream, in frames received
+    pub last: Option<u64>,
+}
+
+impl Trigger {
+    /// Index of the first edge at or after `from` and before `to`.
+    fn find(&self, samples: &[f32], from: usize, to: usize) -> Option<usize> {
+        let mut ready = false;
+        for (idx, &s) in samples.iter().enumerate().take(to) {
+            let (past, rearm) = match self.edge {
+                Edge::Rising => (s >= self.level, s <= self.level - self.hysteresis),
+                Edge::Falling => (s <= self.level, s >= self.level + self.hysteresis),
+            };
+            if ready && past && idx >= from {
+                return Some(idx);
+            }
+            if rearm {
+                ready = true;
+            } else if past {
+                ready = false;
+            }
+        }
+        None
+    }
+}
+
 pub struct Scope {
-    pub view: Canvas<Window>,
+    pub view: Pane,
     pub zc_search: usize,
     pub zc_horiz: f32,
     pub pow: f32,
+    /// Milliseconds per (horizontal) division; None for a sample per pixel
+    pub timebase: Option<f32>,
+    /// Full scale per (vertical) division
+    pub div: f32,
+    pub layout: Layout,
+    pub phosphor: Phosphor,
+    /// Draw the traces in the phosphor instead of as plain lines
+    pub use_phosphor: bool,
+    /// Color the phosphor by intensity instead of by channel
+    pub heat: bool,
+    /// Per channel scales, for stacked lanes
+    pub divs: Vec<f32>,
+    /// The lane whose scale is adjusted
+    pub selected: usize,
+    /// Draw the min and max of the samples under each pixel column when
+    /// there are several
+    pub envelope: bool,
+    pub rate: f32,
+    /// Lock to the waveform's period instead of triggering
+    pub lock: bool,
+    /// When locked, show exactly this many periods across the screen
+    pub periods: Option<usize>,
+    pub period: Option<f32>,
+    pub trigger: Trigger,
+    /// Per channel, the samples from the last trigger on
+    pub held: Vec<Vec<f32>>,
+    /// As of the last frame, for cycling the trigger source
+    pub channels: usize,
+    pub title: String,
 }
 
-impl View for Scope {
-    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
-        let _g = hprof::enter("Scope::render");
-        self.view.set_draw_color(Color::RGB(0, 0, 0));
-        self.view.clear();
-        self.view.set_blend_mode(BlendMode::Add);
-        let (width, height) = self.view.output_size().expect("getting size");
-        let winsz = info.left.samples.len();
-        let mut zc_mark = (width as f32 * self.zc_horiz) as usize;
-        if zc_mark >= winsz { zc_mark = winsz - 1; }
-        //let mut indices_set: Vec<usize> = Vec::new();
+impl Scope {
+    /// Samples across the screen.
+    fn span(&self, width: u32) -> usize {
+        if let (true, Some(n), Some(p)) = (self.lock, self.periods, self.period) {
+            return std::cmp::max((n as f32 * p).round() as usize, 2);
+        }
+        self.timebase_span(width)
+    }
+
+    /// Samples across the screen by the timebase alone.
+    fn timebase_span(&self, width: u32) -> usize {
+        match self.timebase {
+            Some(ms) if self.rate > 0.0 => std::cmp::max((ms / 1000.0 * DIVS_X as f32 * self.rate) as usize, 2),
+            _ => width as usize,
+        }
+    }
+
+    /// The offset of the newest stretch of `span` samples that lines up
+    /// with the last one shown, within one period; None if the source isn't
+    /// periodic.
+    fn lock_offset(&mut self, info: &Info, span: usize) -> Option<usize> {
+        let src = self.trigger.source.unwrap_or(0) % info.channels.len();
+        let samples = info.channels[src].samples;
+        self.period = pitch::period(samples, info.rate);
+        let period = self.period?.round() as usize;
+        let latest = samples.len().checked_sub(span)?;
+        let earliest = latest.saturating_sub(period);
+        let offset = match self.held.get(src).filter(|h| h.len() >= span) {
+            Some(reference) => {
+                // Compared over a few periods at most, which is plenty
+                let len = std::cmp::min(span, 4 * period);
+                let score = |off: usize| -> f32 {
+                    samples[off .. off + len].iter().zip(&reference[.. len]).map(|(a, b)| a * b).sum()
+                };
+                (earliest ..= latest)
+                    .map(|off| (off, score(off)))
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .unwrap().0
+            },
+            None => (earliest.max(1) ..= latest)
+                .find(|&off| samples[off - 1] < 0.0 && samples[off] >= 0.0)
+                .unwrap_or(latest),
+        };
+        Some(offset)
+    }
+
+    /// Vertical scale (per division) of channel `chan`.
+    fn div_of(&self, chan: usize) -> f32 {
+        match self.layout {
+            Layout::Stacked => self.divs.get(chan).cloned().unwrap_or(self.div),
+            _ => self.div,
+        }
+    }
+
+    fn shape(&self, samp: f32, div: f32) -> f32 {
+        let samp = samp / (div * DIVS_Y as f32 / 2.0);
+        if self.pow == 1.0 { samp } else { samp.signum() * samp.abs().powf(self.pow) }
+    }
+
+    fn to_y(&self, samp: f32, div: f32, lane: Rect) -> i32 {
+        lane.y() + normalize_centered(self.shape(samp, div), lane.height())
+    }
+
+    /// As `to_y`, unrounded, for the phosphor.
+    fn to_y_f(&self, samp: f32, div: f32, lane: Rect) -> f32 {
+        let hh = (lane.height() / 2) as f32;
+        lane.y() as f32 + hh - hh * self.shape(samp, div)
+    }
+
+    fn render_graticule(&mut self, lane: Rect, divs_x: usize, divs_y: usize) {
+        let (x0, y0, w, h) = (lane.x(), lane.y(), lane.width() as usize, lane.height() as usize);
+        self.view.set_draw_color(Color::RGB(31, 31, 31));
+        for i in 1 .. divs_x {
+            let x = x0 + (i * w / divs_x) as i32;
+            self.view.draw_line((x, y0), (x, lane.bottom())).expect("drawing");
+        }
+        for i in 1 .. divs_y {
+            let y = y0 + (i * h / divs_y) as i32;
+            self.view.draw_line((x0, y), (lane.right(), y)).expect("drawing");
+        }
+        // Ticks at fifths of a division along the axes
+        let (cx, cy) = (x0 + w as i32 / 2, y0 + h as i32 / 2);
+        self.view.set_draw_color(Color::RGB(63, 63, 63));
+        for i in 0 .. divs_x * 5 {
+            let x = x0 + (i * w / (divs_x * 5)) as i32;
+            self.view.draw_line((x, cy - 2), (x, cy + 2)).expect("drawing");
+        }
+        for i in 0 .. divs_y * 5 {
+            let y = y0 + (i * h / (divs_y * 5)) as i32;
+            self.view.draw_line((cx - 2, y), (cx + 2, y)).expect("drawing");
+        }
+    }
+
+    /// Channel `idx`'s trace, in its colors (or into its phosphor layer).
+    fn render_trace(&mut self, samps: &[f32], span: usize, lane: Rect, div: f32, idx: usize) {
+        let color = |s: f32| if !(-1.0 ..= 1.0).contains(&s) { clip_color(idx) } else { channel_color(idx) };
+        let samps = &samps[.. std::cmp::min(span, samps.len())];
+        let width = lane.width() as usize;
+        if self.use_phosphor {
+            // The phosphor shows density itself, so there's no envelope
+            let scale = width as f32 / span as f32;
+            for (i, pair) in samps.windows(2).enumerate() {
+                let a = (i as f32 * scale, self.to_y_f(pair[0], div, lane));
+                let b = ((i + 1) as f32 * scale, self.to_y_f(pair[1], div, lane));
+                self.phosphor.line(idx, a, b);
+            }
+            return;
+        }
+        if self.envelope && span > width {
+            // Each column spans from its lowest to its highest sample, and
+            // the last of the column before, so the trace is unbroken
+            for x in 0 .. width {
+                let a = x * span / width;
+                let b = std::cmp::min(std::cmp::max((x + 1) * span / width, a + 1), samps.len());
+                if a >= b {
+                    break;
+                }
+                let (lo, hi) = samps[a.saturating_sub(1) .. b].iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &s| (lo.min(s), hi.max(s)));
+                self.view.set_draw_color(color(if hi.abs() > lo.abs() { hi } else { lo }));
+                self.view.draw_line(
+                    (x as i32, self.to_y(hi, div, lane)),
+                    (x as i32, self.to_y(lo, div, lane)),
+                ).expect("drawing");
+            }
+            return;
+        }
+        let scale = width as f32 / span as f32;
+        for (i, pair) in samps.windows(2).enumerate() {
+            self.view.set_draw_color(color(pair[1]));
+            self.view.draw_line(
+                ((i as f32 * scale) as i32, self.to_y(pair[0], div, lane)),
+                (((i + 1) as f32 * scale) as i32, self.to_y(pair[1], div, lane))
+            ).expect("drawing");
+        }
+    }
+
+    /// The first two channels against each other, in a square.
+    fn render_xy(&mut self, x: &[f32], y: &[f32], span: usize, width: u32, height: u32) {
+        let side = std::cmp::min(width, height);
+        let square = Rect::new((width - side) as i32 / 2, (height - side) as i32 / 2, side, side);
+        self.render_graticule(square, DIVS_Y, DIVS_Y);
+        let half = side as f32 / 2.0;
+        let to_screen = |sx: f32, sy: f32| (
+            square.x() as f32 + half + half * self.shape(sx, self.div),
+            square.y() as f32 + half - half * self.shape(sy, self.div),
+        );
+        let points: Vec<(f32, f32)> = x.iter().zip(y).take(span).map(|(&sx, &sy)| to_screen(sx, sy)).collect();
+        if self.use_phosphor {
+            for pair in points.windows(2) {
+                self.phosphor.line(0, pair[0], pair[1]);
+            }
+            return;
+        }
+        self.view.set_draw_color(channel_color(0));
+        for pair in points.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            self.view.draw_line((a.0 as i32, a.1 as i32), (b.0 as i32, b.1 as i32)).expect("drawing");
+        }
+    }
+
+    // The original minimum-energy search over the sum of all channels
+    fn search(&self, info: &Info, zc_mark: usize) -> usize {
+        let winsz = info.channels[0].samples.len();
         let mut count = 0usize;
         let mut last = -1f32;
         let offset = (0 .. self.zc_search)
             .filter(|&i| zc_mark + i < winsz)
-            .map(|i| (i, zc_mark + i, info.left.samples[zc_mark + i], info.right.samples[zc_mark + i]))
+            .map(|i| (i, zc_mark + i))
             .scan(None,
-                  |state, (i, _ai, l, r)| {
+                  |state, (i, ai)| {
                       count += 1;
-                      let en = l.abs() + r.abs();
-                      let sm = l + r;
+                      let en: f32 = info.channels.iter().map(|c| c.samples[ai].abs()).sum();
+                      let sm: f32 = info.channels.iter().map(|c| c.samples[ai]).sum();
                       let mut ix = 0;
                       if state.is_none() || {
                           let (_li, min, nix) = state.unwrap();
                           ix = nix + 1;
                           en < min && last <= sm
                       } {
-                          //indices_set.push(ai);
                           *state = Some((i, en, ix));
                       }
                       last = sm;
@@ -52,66 +407,168 @@ impl View for Scope {
             )
             .last()
             .unwrap_or((0, 0.0, self.zc_search + 1));
-        //println!("sc {:?} count {}", offset, count);
-        let offset = offset.0;
-        self.view.set_draw_color(Color::RGB(63,0,0));
-        let zcx = width as i32 - zc_mark as i32;
-        self.view.draw_line(
-            (zcx, 0i32),
-            (zcx, height as i32)
-        ).expect("drawing");
-
-        for offs in 0..=1 {
-            let def_color = if offs == 0 {
-                Color::RGB(0,255,0)
-            } else {
-                Color::RGB(0,0,255)
-            };
-            let clip_color = if offs == 0 {
-                Color::RGB(255,0,0)
-            } else {
-                Color::RGB(255,0,255)
-            };
-            
-            let samps = if offs == 0 {
-                &info.left.samples
-            } else {
-                &info.right.samples
-            };
+        offset.0
+    }
+}
 
-            let mut last_samp = 0.0f32;
-            let mapped: Box<dyn Fn(f32) -> f32> = if self.pow == 1.0 {
-                Box::new(|x| x)
-            } else {
-                Box::new(|x| x.signum() * x.abs().powf(self.pow))
-            };
-            for (x, samp) in samps.iter().skip(offset).cloned().enumerate() {
-                /*
-                let ix = x + offset;
-                if indices_set.contains(&ix) {
-                    self.view.set_draw_color(Color::RGB(255,255,255));
-                    self.view.draw_line(
-                        (x as i32, 0i32),
-                        (x as i32, height as i32),
-                    ).expect("drawing");
-                }
-                */
-                if x > 0 {
-                    self.view.set_draw_color(
-                        if samp > 1.0 || samp < -1.0 {
-                            clip_color
-                        } else {
-                            def_color
-                        }
-                    );
-                    self.view.draw_line(
-                        ((x - 1) as i32, normalize_centered(mapped(last_samp), height)),
-                        (x as i32, normalize_centered(mapped(samp), height))
-                    ).expect("drawing");
+impl View for Scope {
+    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
+        let _g = hprof::enter("Scope::render");
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.clear();
+        self.view.set_blend_mode(BlendMode::Add);
+        let (width, height) = self.view.output_size().expect("getting size");
+        self.rate = info.rate;
+        self.channels = info.channels.len();
+        self.divs.resize(self.channels, self.div);
+        let span = self.span(width);
+        let winsz = info.channels[0].samples.len();
+        let mut zc_mark = (span as f32 * self.zc_horiz) as usize;
+        if zc_mark >= winsz { zc_mark = winsz - 1; }
+
+        // Edges are looked for from zc_mark on, at least the holdoff after
+        // the last trigger
+        let start = info.received.saturating_sub(winsz as u64);
+        let from = match self.trigger.last {
+            Some(last) => {
+                let earliest = last + (self.trigger.holdoff.max(0.0) * info.rate) as u64;
+                std::cmp::max(zc_mark, earliest.saturating_sub(start) as usize)
+            },
+            None => zc_mark,
+        };
+        let holding = from > zc_mark + self.zc_search;
+        let listening = !holding && (self.trigger.mode != Mode::Single || self.trigger.armed);
+        let locked = if self.lock { self.lock_offset(info, span) } else { None };
+        let found = if locked.is_some() {
+            locked
+        } else if !listening {
+            None
+        } else {
+            match self.trigger.source {
+                Some(src) => {
+                    let samples = info.channels[src % info.channels.len()].samples;
+                    self.trigger.find(samples, from, zc_mark + self.zc_search)
+                        .map(|idx| idx - zc_mark)
+                },
+                None => Some(self.search(info, zc_mark)),
+            }
+        };
+        if let Some(offset) = found {
+            self.held = info.channels.iter().map(|c| c.samples[offset ..].to_vec()).collect();
+            self.trigger.last = Some(start + (zc_mark + offset) as u64);
+            self.trigger.armed = false;
+        }
+        let waiting = found.is_none() && listening && self.trigger.mode != Mode::Auto;
+        let free_run = found.is_none() && !holding && self.trigger.mode == Mode::Auto;
+
+        let held = std::mem::take(&mut self.held);
+        let traces: Vec<&[f32]> = info.channels.iter().enumerate()
+            .filter_map(|(idx, chan)| if free_run { Some(chan.samples) } else { held.get(idx).map(|h| &h[..]) })
+            .collect();
+        let lanes: Vec<Rect> = match self.layout {
+            Layout::Stacked => (0 .. self.channels)
+                .map(|idx| {
+                    let (top, bottom) = (idx as u32 * height / self.channels as u32, (idx as u32 + 1) * height / self.channels as u32);
+                    Rect::new(0, top as i32, width, std::cmp::max(bottom - top, 1))
+                })
+                .collect(),
+            _ => vec![Rect::new(0, 0, width, height); self.channels],
+        };
+
+        if self.use_phosphor {
+            self.phosphor.begin(width, height, self.channels);
+        }
+        if self.layout == Layout::XY && traces.len() >= 2 {
+            self.render_xy(traces[0], traces[1], span, width, height);
+        } else {
+            let divs_y = if self.layout == Layout::Stacked { std::cmp::max(DIVS_Y / self.channels, 2) } else { DIVS_Y };
+            for &lane in lanes.iter().take(if self.layout == Layout::Stacked { lanes.len() } else { 1 }) {
+                self.render_graticule(lane, DIVS_X, divs_y);
+            }
+
+            // The trigger point, and for an edge trigger its level
+            let zcx = (width as f32 * self.zc_horiz) as i32;
+            self.view.set_draw_color(if waiting { Color::RGB(127, 0, 0) } else { Color::RGB(63, 0, 0) });
+            self.view.draw_line(
+                (zcx, 0i32),
+                (zcx, height as i32)
+            ).expect("drawing");
+            if let Some(src) = self.trigger.source {
+                let src = src % info.channels.len();
+                let color = channel_color(src);
+                let y = self.to_y(self.trigger.level, self.div_of(src), lanes[src]);
+                self.view.set_draw_color(Color::RGB(color.r / 3, color.g / 3, color.b / 3));
+                self.view.draw_line((0, y), (width as i32, y)).expect("drawing");
+            }
+
+            for (idx, samps) in traces.iter().enumerate() {
+                self.render_trace(samps, span, lanes[idx], self.div_of(idx), idx);
+            }
+
+            // Each lane is labeled by a swatch of its channel's color, the
+            // selected one outlined, and its name
+            if self.layout == Layout::Stacked {
+                for (idx, lane) in lanes.iter().enumerate() {
+                    self.view.set_draw_color(channel_color(idx));
+                    self.view.fill_rect(Rect::new(2, lane.y() + 2, 8, 8)).expect("drawing");
+                    if idx == self.selected % self.channels {
+                        self.view.draw_rect(Rect::new(0, lane.y(), 12, 12)).expect("drawing");
+                    }
+                    text::draw_text(&mut self.view, info.channels[idx].name, (16, lane.y() + 6), (Align::Start, Align::Middle), channel_color(idx)).expect("drawing text");
                 }
-                last_samp = samp;
             }
         }
+        drop(traces);
+        self.held = held;
+        if self.use_phosphor {
+            let maps: Vec<ColorMap> = (0 .. self.channels)
+                .map(|idx| if self.heat { ColorMap::Heat } else { ColorMap::Tint(channel_color(idx)) })
+                .collect();
+            self.phosphor.draw(&mut self.view, &maps);
+        }
+
+        let source = match self.trigger.source {
+            Some(src) => format!(
+                "{} {} at {:.2}",
+                info.channels[src % info.channels.len()].name,
+                if self.trigger.edge == Edge::Rising { "rising" } else { "falling" },
+                self.trigger.level,
+            ),
+            None => "search".to_string(),
+        };
+        let state = match self.trigger.mode {
+            _ if waiting => "waiting for trigger",
+            Mode::Single if !self.trigger.armed => "stopped",
+            Mode::Auto if free_run => "auto (untriggered)",
+            Mode::Auto => "auto",
+            Mode::Normal => "normal",
+            Mode::Single => "single",
+        };
+        let time = match self.timebase {
+            Some(ms) => format!("{} ms/div", ms),
+            None => format!("{:.3} ms/div", span as f32 / DIVS_X as f32 / info.rate * 1000.0),
+        };
+        let sync = match (self.lock, self.period) {
+            (true, Some(p)) => format!("locked to {:.1} Hz", info.rate / p),
+            (true, None) => format!("no pitch; trigger {}, {}", source, state),
+            _ => format!("trigger {}, {}", source, state),
+        };
+        let names: Vec<&str> = info.channels.iter().map(|c| c.name).collect();
+        let layout = match self.layout {
+            Layout::Overlaid => names.join(" + "),
+            Layout::Stacked => format!("{} ({} selected)", names.join(" / "), names[self.selected % names.len()]),
+            Layout::XY => format!("{} vs {}", names.get(1).unwrap_or(&"-"), names[0]),
+        };
+        let div = self.div_of(self.selected % self.channels);
+        let scales = format!("{}, {} FS/div ({:.1} dBFS)", time, div, 20.0 * div.log10());
+        let bottom = height as i32 - 2;
+        text::draw_text(&mut self.view, &scales, (2, bottom), (Align::Start, Align::End), LABEL_COLOR).expect("drawing text");
+        text::draw_text(&mut self.view, &sync, (width as i32 - 2, bottom), (Align::End, Align::End), LABEL_COLOR).expect("drawing text");
+        let title = format!("scope: {}; {}; {}", layout, scales, sync);
+        if title != self.title {
+            self.view.set_title(&title).expect("setting title");
+            self.title = title;
+        }
 
         drop(_g);
 
@@ -120,6 +577,69 @@ impl View for Scope {
     }
 
     fn requested_window(&self) -> usize {
-        self.view.output_size().expect("getting output size").0 as usize + self.zc_search
+        let search = if self.lock && self.rate > 0.0 {
+            // Enough for the autocorrelation and a period of alignment
+            std::cmp::max(self.zc_search, 2 * pitch::max_lag(self.rate))
+        } else {
+            self.zc_search
+        };
+        let width = self.view.output_size().expect("getting output size").0;
+        let span = match (self.lock, self.periods) {
+            // The most that many periods can span, so the window doesn't
+            // change with every new estimate
+            (true, Some(n)) if self.rate > 0.0 => std::cmp::max(n * pitch::max_lag(self.rate), self.timebase_span(width)),
+            _ => self.span(width),
+        };
+        span + search
+    }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+
+    fn key(&mut self, key: Keycode) {
+        match key {
+            // Cycles from the search through each channel
+            Keycode::T => self.trigger.source = match self.trigger.source {
+                None => Some(0),
+                Some(src) if src + 1 < self.channels => Some(src + 1),
+                Some(_) => None,
+            },
+            Keycode::E => self.trigger.edge = match self.trigger.edge {
+                Edge::Rising => Edge::Falling,
+                Edge::Falling => Edge::Rising,
+            },
+            Keycode::M => self.trigger.mode = self.trigger.mode.next(),
+            Keycode::Up => self.trigger.level += LEVEL_STEP,
+            Keycode::Down => self.trigger.level -= LEVEL_STEP,
+            Keycode::Space => self.trigger.armed = true,
+            Keycode::LeftBracket | Keycode::RightBracket => {
+                let ms = self.timebase.unwrap_or_else(|| {
+                    let width = self.view.output_size().expect("getting output size").0;
+                    step_125(self.span(width) as f32 / DIVS_X as f32 / self.rate * 1000.0, false)
+                });
+                self.timebase = Some(step_125(ms, key == Keycode::RightBracket));
+            },
+            Keycode::Minus | Keycode::Equals => {
+                let up = key == Keycode::Minus;
+                match self.layout {
+                    Layout::Stacked if !self.divs.is_empty() => {
+                        let idx = self.selected % self.divs.len();
+                        self.divs[idx] = step_125(self.divs[idx], up);
+                    },
+                    _ => self.div = step_125(self.div, up),
+                }
+            },
+            Keycode::S => self.layout = self.layout.next(),
+            Keycode::Tab => self.selected = (self.selected + 1) % std::cmp::max(self.channels, 1),
+            Keycode::V => self.envelope = !self.envelope,
+            Keycode::L => self.lock = !self.lock,
+            Keycode::P => {
+                self.use_phosphor = !self.use_phosphor;
+                self.phosphor.clear();
+            },
+            Keycode::H => self.heat = !self.heat,
+            _ => (),
+        }
     }
 }
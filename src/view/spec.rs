@@ -1,41 +1,228 @@
-use super::{Info, View};
+use super::{Info, View, channel_color};
+use super::pane::Pane;
+use super::text::{self, Align};
+use super::texture::OwnedTexture;
+use crate::harmonics;
+use crate::weighting::Weighting;
 
+use std::time::Instant;
+
+use sdl2::keyboard::Keycode;
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::video::Window;
-use sdl2::render::{Canvas, BlendMode, Texture, TextureAccess};
+use sdl2::render::BlendMode;
+
+/// What the waterfall shows: each bin where it falls, or its energy moved to
+/// its reassigned frequency and time (or, synchrosqueezed, frequency only).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waterfall {
+    Plain,
+    Reassigned,
+    Squeezed,
+}
+
+impl Waterfall {
+    pub fn next(self) -> Waterfall {
+        match self {
+            Waterfall::Plain => Waterfall::Reassigned,
+            Waterfall::Reassigned => Waterfall::Squeezed,
+            Waterfall::Squeezed => Waterfall::Plain,
+        }
+    }
+}
+
+const PEAKS_LISTED: usize = 3;
+/// Least room between frequency labels, in pixels
+const LABEL_GAP: i32 = 8;
 
 pub struct Spec {
-    pub view: Canvas<Window>,
+    pub view: Pane,
     pub db_bias: f32,
     pub db_range: f32,
     pub waterfall_sz: f32,
+    /// The waterfall's rows, as a ring (matching the texture)
     pub waterfall_data: Option<Vec<u8>>,
-    pub waterfall_tex: *mut sdl2_sys::SDL_Texture,
+    pub waterfall_tex: Option<OwnedTexture>,
+    /// Where the newest row is in the ring
+    pub waterfall_head: usize,
+    pub harmonics: usize,
+    pub show_harmonics: bool,
+    pub harm_chan: usize,
+    pub weighting: Weighting,
+    pub weights: Vec<f32>,
+    /// Show calibrated channels in dB SPL
+    pub spl: bool,
+    /// Plot the constant-Q spectrum (if main computes one) instead of the FFT
+    pub constant_q: bool,
+    pub cq_weights: Vec<f32>,
+    pub waterfall: Waterfall,
+    /// Whether main reassigns the spectrum, without which the waterfall is
+    /// always plain
+    pub can_reassign: bool,
+    pub last_frame: Option<Instant>,
+    pub show_peaks: bool,
+    pub title: String,
+    /// The last error drawing, reported once
+    pub error: Option<String>,
 }
 
 impl Spec {
-    fn rebuild_texture(&mut self, w: usize, h: usize) {
+    fn rebuild_texture(&mut self, w: usize, h: usize) -> Result<(), String> {
         let _g = hprof::enter("rebuild_texture");
         self.waterfall_data = Some(vec![0u8; w * h * 4]);
-        let tc = self.view.texture_creator();
-        let wf = tc.create_texture(
-            PixelFormatEnum::RGBA8888,
-            TextureAccess::Streaming,
-            w as u32, h as u32
-        ).expect("creating spec backing texture");
-        if !self.waterfall_tex.is_null() {
-            unsafe { sdl2_sys::SDL_DestroyTexture(self.waterfall_tex) };
-        }
-        self.waterfall_tex = wf.raw();
-        std::mem::forget(wf);
+        self.waterfall_head = 0;
+        // The old texture goes first, so there's only ever one
+        self.waterfall_tex = None;
+        self.waterfall_tex = Some(OwnedTexture::new(&self.view, PixelFormatEnum::RGBA8888, w as u32, h as u32)?);
+        Ok(())
     }
-}
 
-impl View for Spec {
-    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
-        let _g = hprof::enter("Spec::render");
-        let (width, height) = self.view.output_size().expect("getting size");
+    fn using_cq(&self, info: &Info) -> bool {
+        self.constant_q && info.cqt.is_some()
+    }
+
+    /// Horizontal position of `freq`: exponential in frequency for the FFT,
+    /// linear in constant-Q bins (so in pitch) otherwise
+    fn freq_x(&self, info: &Info, freq: f32, width: u32) -> i32 {
+        let normx = match info.cqt {
+            Some(cqt) if self.constant_q => {
+                let freqs = cqt.freqs();
+                (freq / freqs[0]).log2() * cqt.bins_per_octave as f32 / freqs.len() as f32
+            },
+            _ => (1.0 + 2.0 * freq / info.rate).log2(),
+        };
+        (normx * width as f32) as i32
+    }
+
+    /// Where row `row` of the waterfall as shown (`water_y` the newest, at
+    /// the bottom) is in the ring.
+    fn ring_row(&self, row: usize, water_y: u32) -> usize {
+        (self.waterfall_head + 1 + row) % (water_y as usize + 1)
+    }
+
+    /// Add a channel's reassigned energy to the waterfall: each point at its
+    /// reassigned frequency and, unless synchrosqueezing, in the row of its
+    /// reassigned time (`hop` samples per row). Returns the highest row
+    /// (as shown) it changed.
+    fn splat(&mut self, info: &Info, chan: usize, offset: f32, hop: f32, width: u32, water_y: u32) -> usize {
+        let _g = hprof::enter("splat");
+        let (bias, range) = (self.db_bias / 10f32, self.db_range / 10f32);
+        let ci = &info.channels[chan];
+        let color = channel_color(chan);
+        let bins = ci.spectrum.len();
+        let mut top = water_y as usize;
+        for p in ci.reassigned {
+            let x = self.freq_x(info, p.bin * info.rate / bins as f32, width);
+            if x < 0 || x >= width as i32 {
+                continue;
+            }
+            let k = std::cmp::min(p.bin.max(0.0).round() as usize, self.weights.len() - 1);
+            let specval = 0.5 * p.power.log10() + self.weights[k] + offset;
+            let a = (1.0 + (bias + specval) / range).clamp(0.0, 1.0);
+            if a <= 0.0 {
+                continue;
+            }
+            let row = if self.waterfall == Waterfall::Reassigned && hop > 0.0 {
+                (water_y as f32 + (p.time / hop).round()).clamp(0.0, water_y as f32) as usize
+            } else {
+                water_y as usize
+            };
+            top = std::cmp::min(top, row);
+            let at = (self.ring_row(row, water_y) * width as usize + x as usize) * 4;
+            let px = &mut self.waterfall_data.as_mut().unwrap()[at .. at + 4];
+            for (byte, comp) in px[1 ..].iter_mut().zip([color.b, color.g, color.r]) {
+                *byte = byte.saturating_add((a * comp as f32) as u8);
+            }
+        }
+        top
+    }
+
+    /// Tick and label frequencies along the top of the graph (at `y`), from
+    /// the 1-2-5 sequence, leaving out those that would crowd the last.
+    fn render_axis(&mut self, info: &Info, width: u32, y: i32) -> Result<(), String> {
+        let color = Color::RGB(127, 127, 127);
+        let mut clear_from = i32::MIN;
+        let mut decade = 10.0;
+        while decade < info.rate / 2.0 {
+            for freq in [decade, 2.0 * decade, 5.0 * decade] {
+                let x = self.freq_x(info, freq, width);
+                if x < 0 || x >= width as i32 {
+                    continue;
+                }
+                let label = if freq >= 1000.0 { format!("{}k", freq / 1000.0) } else { format!("{}", freq) };
+                let half = text::text_size(&label).0 as i32 / 2;
+                if x - half < clear_from {
+                    continue;
+                }
+                self.view.set_draw_color(color);
+                self.view.draw_line((x, y), (x, y + 3))?;
+                text::draw_text(&mut self.view, &label, (x, y + 5), (Align::Middle, Align::Start), color)?;
+                clear_from = x + half + LABEL_GAP;
+            }
+            decade *= 10.0;
+        }
+        Ok(())
+    }
+
+    /// Tick the analyzed channel's peaks at the top of the graph, as long as
+    /// they are loud, and list the first few.
+    fn render_peaks(&mut self, info: &Info, width: u32, graph_y: u32, height: u32) -> Result<String, String> {
+        let chan = &info.channels[self.harm_chan % info.channels.len()];
+        let tick = (height - graph_y) as f32 / 4.0;
+        for p in chan.peaks {
+            let x = self.freq_x(info, p.freq, width);
+            let len = (tick * ((p.level + 120.0) / 120.0).clamp(0.0, 1.0)) as i32;
+            self.view.set_draw_color(Color::RGB(255, 255, 255));
+            self.view.draw_line((x, graph_y as i32), (x, graph_y as i32 + len))?;
+        }
+        let list: Vec<String> = chan.peaks.iter()
+            .take(PEAKS_LISTED)
+            .map(|p| format!("{:.2} Hz {:.1} dB", p.freq, p.level))
+            .collect();
+        Ok(format!("{} peaks: {}", chan.name, list.join(", ")))
+    }
+
+    fn render_harmonics(&mut self, info: &Info, width: u32, graph_y: u32, height: u32) -> Result<String, String> {
+        let chan = &info.channels[self.harm_chan % info.channels.len()];
+        let analysis = harmonics::analyze(chan.spectrum, info.rate, info.window, self.harmonics);
+        let text = match &analysis {
+            Some(a) => format!(
+                "{}: f0 {:.1} Hz, THD {:.4}%, THD+N {:.4}%, SINAD {:.1} dB, SNR {:.1} dB, ENOB {:.1}",
+                chan.name, a.harmonics[0].freq, a.thd * 100.0, a.thd_n * 100.0, a.sinad, a.snr, a.enob,
+            ),
+            None => format!("{}: no tone", chan.name),
+        };
+
+        // Mark each harmonic at its position in the (exponential) frequency
+        // axis, brighter the stronger it is (over 120dB)
+        for h in analysis.iter().flat_map(|a| a.harmonics.iter()) {
+            let x = self.freq_x(info, h.freq, width);
+            let lvl = (64.0 + 191.0 * ((h.level + 120.0) / 120.0).clamp(0.0, 1.0)) as u8;
+            self.view.set_draw_color(if h.order == 1 { Color::RGB(lvl, lvl, lvl) } else { Color::RGB(lvl, lvl * 3 / 4, 0) });
+            self.view.draw_line((x, graph_y as i32), (x, height as i32))?;
+        }
+        Ok(text)
+    }
+
+    fn draw(&mut self, info: &Info) -> Result<(), String> {
+        let (width, height) = self.view.output_size()?;
+
+        let bins = info.channels[0].spectrum.len();
+        if self.weights.len() != bins / 2 {
+            // Kept in the same units as specval below: log10 of amplitude
+            self.weights = self.weighting.table(bins, info.rate).iter().map(|db| db / 20.0).collect();
+        }
+
+        if let Some(cqt) = info.cqt {
+            if self.cq_weights.len() != cqt.freqs().len() {
+                self.cq_weights = cqt.freqs().iter().map(|&f| self.weighting.gain(f) / 20.0).collect();
+            }
+        }
+        let constant_q = self.using_cq(info);
+
+        let now = Instant::now();
+        let hop = self.last_frame.map_or(0.0, |t| (now - t).as_secs_f32() * info.rate);
+        self.last_frame = Some(now);
 
         let bias = self.db_bias / 10f32;
         let range = self.db_range / 10f32;
@@ -46,85 +233,172 @@ impl View for Spec {
 
         if let Some(d) = &self.waterfall_data {
             if d.len() != width as usize * water_height as usize * 4 {
-                self.rebuild_texture(width as usize, water_height as usize);
+                self.rebuild_texture(width as usize, water_height as usize)?;
             }
         } else {
-            self.rebuild_texture(width as usize, water_height as usize);
+            self.rebuild_texture(width as usize, water_height as usize)?;
         }
 
         self.view.set_draw_color(Color::RGB(0,0,0));
-        self.view.fill_rect(Rect::new(0, water_y as i32, width, graph_height + 1)).expect("clearing");
+        self.view.fill_rect(Rect::new(0, water_y as i32, width, graph_height + 1))?;
         self.view.set_blend_mode(BlendMode::Add);
 
-        // Move up the waterfall
+        // Move up the waterfall, by turning the ring a row: the oldest
+        // becomes the newest
         let g2 = hprof::enter("waterfall");
-        self.waterfall_data
-            .as_mut()
-            .unwrap()
-            .copy_within(width as usize * 4.., 0);
-        let lw = self.waterfall_data.as_ref().unwrap().len();
-        (&mut self.waterfall_data.as_mut().unwrap()[lw - width as usize * 4 ..]).fill(0u8);
-
-        for chan in 0 ..= 1 {
-            let spec = if chan == 0 {
-                self.view.set_draw_color(Color::RGB(0,255,0));
-                &info.left.spectrum
-            } else {
-                self.view.set_draw_color(Color::RGB(0,0,255));
-                &info.right.spectrum
+        let pitch = width as usize * 4;
+        self.waterfall_head = (self.waterfall_head + 1) % water_height as usize;
+        let wd_offset = self.waterfall_head * pitch;
+        self.waterfall_data.as_mut().unwrap()[wd_offset .. wd_offset + pitch].fill(0u8);
+        let mut dirty_from = water_y as usize;
+
+        // In SPL, levels are first normalized to dBFS, then offset; all in
+        // the same units as specval
+        let dbfs = 10.0 * (2.0 * info.window.power_scale()).log10();
+        for (chan, ci) in info.channels.iter().enumerate() {
+            let color = channel_color(chan);
+            let offset = match ci.spl {
+                Some(o) if self.spl => (dbfs + o) / 20.0,
+                _ => 0.0,
             };
+            self.view.set_draw_color(color);
+            let spec = &ci.spectrum;
+            let reassigning = self.waterfall != Waterfall::Plain && !ci.reassigned.is_empty();
 
             let mut last_y = 0i32;
-            let wd_offset = water_y as usize * width as usize * 4;
             for x in 0..width {
                 let nonsdl = hprof::enter("inner loop");
                 // Since this is an RFFT, only half the spec is useful
                 let normx = x as f32 / width as f32;
                 //let specidx = (normx * spec.len() as f32 / 2f32) as usize;
-                let specidx = ((2f32.powf(normx) - 1f32) * spec.len() as f32 / 2f32) as usize;
-                let specval = spec[specidx].norm();
-                let specval = if specval == 0.0 {
-                    -1000.0
+                let specval = if constant_q {
+                    let cqidx = std::cmp::min((normx * ci.cq.len() as f32) as usize, ci.cq.len() - 1);
+                    let power = ci.cq[cqidx];
+                    if power == 0.0 {
+                        -1000.0
+                    } else {
+                        // Brought into the FFT's units: dBFS less its normalization
+                        (10.0 * (2.0 * power).log10() - dbfs) / 20.0 + self.cq_weights[cqidx] + offset
+                    }
                 } else {
-                    specval.log10()
+                    let specidx = ((2f32.powf(normx) - 1f32) * spec.len() as f32 / 2f32) as usize;
+                    let specval = spec[specidx].norm();
+                    if specval == 0.0 {
+                        -1000.0
+                    } else {
+                        specval.log10() + self.weights[std::cmp::min(specidx, self.weights.len() - 1)] + offset
+                    }
                 };
                 // println!("debug: wh {} bias {} gh {} range {} specval {}", water_height as i32, bias, graph_height as f32, range, specval);
                 let mut specy = ((bias + specval) * -(graph_height as f32) / range) as i32;
                 if specy > graph_height as i32 { specy = graph_height as i32; }
                 if specy < 0 { specy = 0; }
-                {
+                if !reassigning {
                     let a = 1f32 - (specy as f32 / graph_height as f32);
                     let win = &mut self.waterfall_data.as_mut().unwrap()[wd_offset + x as usize * 4 .. wd_offset + (x+1) as usize * 4];
-                    win[1 - chan + 1] = (a * 255f32) as u8;
+                    // RGBA8888 is stored ABGR in (little-endian) memory
+                    for (byte, comp) in win[1 ..].iter_mut().zip([color.b, color.g, color.r]) {
+                        *byte = byte.saturating_add((a * comp as f32) as u8);
+                    }
                 }
                 drop(nonsdl);
                 if x > 0 {
                     self.view.draw_line(
                         ((x - 1) as i32, last_y),
                         (x as i32, water_height as i32 + specy)
-                    ).expect("drawing");
+                    )?;
                 }
                 last_y = water_height as i32 + specy;
             }
+            if reassigning {
+                dirty_from = std::cmp::min(dirty_from, self.splat(info, chan, offset, hop, width, water_y));
+            }
         }
 
         drop(g2);
 
-        let mut wf: Texture<'static> = unsafe { std::mem::transmute(self.waterfall_tex) };
-        wf.update(
-            None,
-            &self.waterfall_data.as_ref().unwrap(),
-            width as usize * 4,
-        )
-        .expect("uploading");
-        self.view
-            .copy(&wf, None, Some(Rect::new(0, 0, width, water_height)))
-            .expect("blitting");
-        std::mem::forget(wf);
+        let mut units = self.weighting.name();
+        if self.spl && info.channels.iter().any(|ci| ci.spl.is_some()) {
+            units += ", dB SPL";
+        }
+        if constant_q {
+            units += ", constant-Q";
+        }
+        let reassigned = info.channels.iter().any(|ci| !ci.reassigned.is_empty());
+        match self.waterfall {
+            _ if !reassigned => (),
+            Waterfall::Reassigned => units += ", reassigned",
+            Waterfall::Squeezed => units += ", synchrosqueezed",
+            Waterfall::Plain => (),
+        }
+        let mut title = format!("spec ({})", units);
+        if self.show_peaks && info.channels.iter().any(|ci| !ci.peaks.is_empty()) {
+            title = format!("{} {}", title, self.render_peaks(info, width, water_height, height)?);
+        }
+        if self.show_harmonics {
+            title = format!("{} {}", title, self.render_harmonics(info, width, water_height, height)?);
+        }
+        if title != self.title {
+            self.view.set_title(&title).map_err(|e| e.to_string())?;
+            self.title = title;
+        }
+
+        // Only the rows changed this frame are uploaded; the texture is
+        // shown in two parts, split at the newest row, so the oldest is at
+        // the top
+        let rows: Vec<usize> = (dirty_from ..= water_y as usize).map(|r| self.ring_row(r, water_y)).collect();
+        let wf = self.waterfall_tex.as_mut().unwrap();
+        let data = self.waterfall_data.as_ref().unwrap();
+        for row in rows {
+            wf.update(Rect::new(0, row as i32, width, 1), &data[row * pitch .. (row + 1) * pitch], pitch)?;
+        }
+        let head = self.waterfall_head as u32;
+        let older = water_y - head;
+        if older > 0 {
+            self.view.copy(wf.texture(), Rect::new(0, head as i32 + 1, width, older), Rect::new(0, 0, width, older))?;
+        }
+        self.view.copy(wf.texture(), Rect::new(0, 0, width, head + 1), Rect::new(0, older as i32, width, head + 1))?;
+        self.render_axis(info, width, water_height as i32)
+    }
+}
+
+impl View for Spec {
+    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
+        let _g = hprof::enter("Spec::render");
+        match self.draw(info) {
+            Ok(()) => self.error = None,
+            Err(e) => {
+                if self.error.as_ref() != Some(&e) {
+                    eprintln!("spec: {}", e);
+                }
+                self.error = Some(e);
+            },
+        }
 
         drop(_g);
 
         self.view.set_blend_mode(BlendMode::None);
         self.view.present();
     }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+
+    fn key(&mut self, key: Keycode) {
+        match key {
+            Keycode::H => self.show_harmonics = !self.show_harmonics,
+            Keycode::W => {
+                self.weighting.curve = self.weighting.curve.next();
+                self.weights.clear();
+                self.cq_weights.clear();
+            },
+            Keycode::Q => self.constant_q = !self.constant_q,
+            Keycode::R if self.can_reassign => self.waterfall = self.waterfall.next(),
+            Keycode::P => self.show_peaks = !self.show_peaks,
+            Keycode::C => self.harm_chan += 1,
+            Keycode::S => self.spl = !self.spl,
+            _ => (),
+        }
+    }
 }
@@ -0,0 +1,50 @@
+use super::pane::Pane;
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator};
+use sdl2::video::WindowContext;
+
+/// A streaming texture that a view can keep from frame to frame. With sdl2's
+/// `unsafe_textures`, textures don't borrow their creator; this one holds it
+/// instead, which keeps the renderer alive until the texture is destroyed
+/// (on drop).
+pub struct OwnedTexture {
+    texture: Option<Texture>,
+    _creator: TextureCreator<WindowContext>,
+    width: u32,
+    height: u32,
+}
+
+impl OwnedTexture {
+    pub fn new(pane: &Pane, format: PixelFormatEnum, width: u32, height: u32) -> Result<OwnedTexture, String> {
+        let creator = pane.texture_creator();
+        let texture = creator.create_texture_streaming(format, width, height).map_err(|e| e.to_string())?;
+        Ok(OwnedTexture { texture: Some(texture), _creator: creator, width, height })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn texture(&self) -> &Texture {
+        self.texture.as_ref().unwrap()
+    }
+
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.texture.as_mut().unwrap().set_blend_mode(mode);
+    }
+
+    pub fn update<R: Into<Option<Rect>>>(&mut self, rect: R, pixels: &[u8], pitch: usize) -> Result<(), String> {
+        self.texture.as_mut().unwrap().update(rect, pixels, pitch).map_err(|e| e.to_string())
+    }
+}
+
+impl Drop for OwnedTexture {
+    fn drop(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            // The creator, dropped after this, still holds the renderer
+            unsafe { texture.destroy() };
+        }
+    }
+}
@@ -1,42 +1,326 @@
 use super::{Info, View};
-use super::scope::normalize_centered;
+use super::phosphor::{ColorMap, Phosphor};
+use super::pane::Pane;
+use super::text::{self, Align};
 
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::str::FromStr;
+use std::time::Instant;
+
+use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
-use sdl2::video::Window;
-use sdl2::render::{Canvas, BlendMode};
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+const METER_HEIGHT: u32 = 8;
+const BALANCE_RANGE: f32 = 12.0;
+const POLAR_RANGE: f32 = 48.0;
+/// Time constant of the auto gain's release, in seconds
+const AUTO_RELEASE: f32 = 0.33;
+const AUTO_MAX_GAIN: f32 = 1000.0;
+/// How far out along their axes the channels are named
+const LABEL_RADIUS: f32 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// L on X, R on Y
+    XY,
+    /// Rotated 45 degrees: mid up, side across
+    Goniometer,
+    /// Goniometer folded into the upper half-plane, radius in dB
+    Polar,
+}
+
+impl Mode {
+    fn next(self) -> Mode {
+        match self {
+            Mode::XY => Mode::Goniometer,
+            Mode::Goniometer => Mode::Polar,
+            Mode::Polar => Mode::XY,
+        }
+    }
+
+    /// Map a (gained) sample pair to normalized coordinates: X in -1..1
+    /// rightward, Y in -1..1 upward (0..1 for polar).
+    fn map(self, l: f32, r: f32) -> (f32, f32) {
+        match self {
+            Mode::XY => (l, r),
+            Mode::Goniometer => ((r - l) * FRAC_1_SQRT_2, (l + r) * FRAC_1_SQRT_2),
+            Mode::Polar => {
+                let (mut u, mut v) = Mode::Goniometer.map(l, r);
+                if v < 0.0 {
+                    u = -u;
+                    v = -v;
+                }
+                let mag = (u * u + v * v).sqrt();
+                if mag <= f32::EPSILON {
+                    return (0.0, 0.0);
+                }
+                let rad = ((20.0 * mag.log10() + POLAR_RANGE) / POLAR_RANGE).clamp(0.0, 1.0);
+                (u / mag * rad, v / mag * rad)
+            },
+        }
+    }
+
+    /// Unit vectors of the mono, left, right and anti-phase axes.
+    fn axes(self) -> [(f32, f32); 4] {
+        match self {
+            Mode::XY => [
+                (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+                (1.0, 0.0),
+                (0.0, 1.0),
+                (FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+            ],
+            Mode::Goniometer | Mode::Polar => [
+                (0.0, 1.0),
+                (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+                (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+                (1.0, 0.0),
+            ],
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "xy" => Ok(Mode::XY),
+            "gonio" => Ok(Mode::Goniometer),
+            "polar" => Ok(Mode::Polar),
+            _ => Err(format!("unknown vectorscope mode {} (expected xy, gonio or polar)", s)),
+        }
+    }
+}
+
+/// Phase correlation and L/R balance, exponentially integrated over
+/// `time` seconds of wall-clock time (so it is independent of frame rate).
+pub struct StereoMeter {
+    pub time: f32,
+    lr: f32,
+    ll: f32,
+    rr: f32,
+    last: Option<Instant>,
+}
+
+impl StereoMeter {
+    pub fn new(time: f32) -> StereoMeter {
+        StereoMeter { time, lr: 0.0, ll: 0.0, rr: 0.0, last: None }
+    }
+
+    pub fn update(&mut self, left: &[f32], right: &[f32]) {
+        let n = std::cmp::max(left.len(), 1) as f32;
+        let (mut lr, mut ll, mut rr) = (0f32, 0f32, 0f32);
+        for (&l, &r) in left.iter().zip(right) {
+            lr += l * r;
+            ll += l * l;
+            rr += r * r;
+        }
+        let now = Instant::now();
+        let alpha = match self.last {
+            Some(last) if self.time > 0.0 => 1.0 - (-(now - last).as_secs_f32() / self.time).exp(),
+            _ => 1.0,
+        };
+        self.last = Some(now);
+        self.lr += alpha * (lr / n - self.lr);
+        self.ll += alpha * (ll / n - self.ll);
+        self.rr += alpha * (rr / n - self.rr);
+    }
+
+    /// -1 (anti-phase) to +1 (mono); 0 for silence.
+    pub fn correlation(&self) -> f32 {
+        let den = (self.ll * self.rr).sqrt();
+        if den <= f32::EPSILON { 0.0 } else { (self.lr / den).clamp(-1.0, 1.0) }
+    }
+
+    /// Level of right relative to left, in dB.
+    pub fn balance(&self) -> f32 {
+        if self.ll <= f32::EPSILON || self.rr <= f32::EPSILON {
+            return 0.0;
+        }
+        10.0 * (self.rr / self.ll).log10()
+    }
+}
 
 pub struct Vector {
-    pub view: Canvas<Window>,
-    pub fade_rate: u8,
-    pub brightness: u8
+    pub view: Pane,
+    pub chans: (usize, usize),
+    pub phosphor: Phosphor,
+    pub heat: bool,
+    pub mode: Mode,
+    pub auto_gain: bool,
+    pub compensate: bool,
+    pub envelope: f32,
+    /// When `envelope` was last updated
+    pub envelope_at: Option<Instant>,
+    pub meter: Option<StereoMeter>,
+    pub title: String,
+}
+
+impl Vector {
+    fn to_screen(&self, uv: (f32, f32), width: u32, height: u32) -> (i32, i32) {
+        let (x, y) = self.to_screen_f(uv, width, height);
+        (x as i32, y as i32)
+    }
+
+    /// As `to_screen`, unrounded, for the phosphor.
+    fn to_screen_f(&self, (u, v): (f32, f32), width: u32, height: u32) -> (f32, f32) {
+        let (hw, hh) = (width as f32 / 2.0, height as f32 / 2.0);
+        match self.mode {
+            Mode::Polar => (hw + u * hw, height as f32 - v * height as f32),
+            _ => (hw + u * hw, hh - v * hh),
+        }
+    }
+
+    fn render_graticule(&mut self, width: u32, height: u32) {
+        self.view.set_blend_mode(BlendMode::None);
+        let colors = [
+            Color::RGB(63, 63, 63),
+            Color::RGB(0, 63, 0),
+            Color::RGB(0, 0, 95),
+            Color::RGB(63, 0, 0),
+        ];
+        for (&(u, v), color) in self.mode.axes().iter().zip(colors) {
+            self.view.set_draw_color(color);
+            let (from, to) = match self.mode {
+                Mode::Polar if u == 1.0 => ((-1.0, 0.0), (1.0, 0.0)),
+                Mode::Polar => ((0.0, 0.0), (u, v)),
+                _ => ((-u, -v), (u, v)),
+            };
+            self.view.draw_line(
+                self.to_screen(from, width, height),
+                self.to_screen(to, width, height),
+            ).expect("drawing");
+        }
+    }
+
+    /// Name the channels at the ends of their axes.
+    fn render_labels(&mut self, names: (&str, &str), width: u32, height: u32) {
+        let axes = self.mode.axes();
+        for (name, (u, v), color) in [(names.0, axes[1], Color::RGB(0, 127, 0)), (names.1, axes[2], Color::RGB(63, 63, 191))] {
+            let at = self.to_screen((u * LABEL_RADIUS, v * LABEL_RADIUS), width, height);
+            text::draw_text(&mut self.view, name, at, (Align::Middle, Align::Middle), color).expect("drawing text");
+        }
+    }
+
+    fn render_meter(&mut self, names: (&str, &str), width: u32, height: u32) {
+        let meter = match &self.meter {
+            Some(m) => m,
+            None => return,
+        };
+        let (corr, bal) = (meter.correlation(), meter.balance());
+        let mid = width as i32 / 2;
+        self.view.set_blend_mode(BlendMode::None);
+
+        // Correlation along the bottom, from the center
+        let y = (height - METER_HEIGHT) as i32;
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.fill_rect(Rect::new(0, y, width, METER_HEIGHT)).expect("clearing");
+        let cx = mid + (corr * mid as f32) as i32;
+        self.view.set_draw_color(if corr < 0.0 { Color::RGB(255, 0, 0) } else { Color::RGB(0, 191, 0) });
+        self.view.fill_rect(Rect::new(
+            std::cmp::min(mid, cx), y + 1, std::cmp::max((cx - mid).unsigned_abs(), 1), METER_HEIGHT - 2,
+        )).expect("drawing");
+
+        // Balance along the top, as a marker
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.fill_rect(Rect::new(0, 0, width, METER_HEIGHT)).expect("clearing");
+        self.view.set_draw_color(Color::RGB(63, 63, 63));
+        self.view.draw_line((mid, 0), (mid, METER_HEIGHT as i32)).expect("drawing");
+        let bx = mid + (bal.clamp(-BALANCE_RANGE, BALANCE_RANGE) / BALANCE_RANGE * mid as f32) as i32;
+        self.view.set_draw_color(Color::RGB(255, 255, 0));
+        self.view.fill_rect(Rect::new(bx - 2, 1, 5, METER_HEIGHT - 2)).expect("drawing");
+
+        let label = Color::RGB(160, 160, 160);
+        let balance = format!("bal {} {:.1} dB", if bal < 0.0 { "L" } else { "R" }, bal.abs());
+        text::draw_text(&mut self.view, &balance, (2, METER_HEIGHT as i32 + 2), (Align::Start, Align::Start), label).expect("drawing text");
+        text::draw_text(&mut self.view, &format!("corr {:+.2}", corr), (2, y - 2), (Align::Start, Align::End), label).expect("drawing text");
+
+        let title = format!("vec ({}/{}): corr {:+.2}, {}", names.0, names.1, corr, balance);
+        if title != self.title {
+            self.view.set_title(&title).expect("setting title");
+            self.title = title;
+        }
+    }
 }
 
 impl View for Vector {
     fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
         let _g = hprof::enter("Vector::render");
-        self.view
-            .set_draw_color(Color::RGBA(0, 0, 0, self.fade_rate));
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
         self.view.set_blend_mode(BlendMode::None);
-        self.view.fill_rect(None).expect("clearing");
+        self.view.clear();
 
         let (width, height) = self.view.output_size().expect("getting size");
-        self.view.set_draw_color(Color::RGB(0,self.brightness,self.brightness));
-        self.view.set_blend_mode(BlendMode::Add);
+        self.render_graticule(width, height);
+        self.phosphor.begin(width, height, 1);
+
+        let (mut left, mut right) = (info.channels[self.chans.0].samples, info.channels[self.chans.1].samples);
+        if self.compensate {
+            if let Some(lag) = info.delay.and_then(|e| e.lag_between(self.chans.0, self.chans.1)) {
+                // Pair each left sample with the right sample lag later
+                let lag = (lag.round() as isize).clamp(1 - left.len() as isize, left.len() as isize - 1);
+                if lag >= 0 {
+                    left = &left[.. left.len() - lag as usize];
+                    right = &right[lag as usize ..];
+                } else {
+                    left = &left[(-lag) as usize ..];
+                    right = &right[.. right.len() - (-lag) as usize];
+                }
+            }
+        }
+        let gain = if self.auto_gain {
+            let peak = left.iter().chain(right)
+                .fold(0f32, |pk, s| pk.max(s.abs()));
+            let now = Instant::now();
+            let release = self.envelope_at.map_or(0.0, |at| (-(now - at).as_secs_f32() / AUTO_RELEASE).exp());
+            self.envelope_at = Some(now);
+            self.envelope = peak.max(self.envelope * release);
+            1.0 / self.envelope.max(1.0 / AUTO_MAX_GAIN)
+        } else {
+            1.0
+        };
 
         let mut lastpt = None;
-        for (&x, &y) in info.left.samples.iter().zip(info.right.samples) {
-            let (x, y) = (
-                width as i32 - normalize_centered(x, width),
-                normalize_centered(y, height)
-            );
-            if let Some((lx, ly)) = lastpt {
-                self.view.draw_line((lx, ly), (x, y)).expect("drawing");
+        for (&l, &r) in left.iter().zip(right) {
+            let pt = self.to_screen_f(self.mode.map(l * gain, r * gain), width, height);
+            if self.mode == Mode::Polar {
+                self.phosphor.point(0, pt);
+            } else if let Some(last) = lastpt {
+                self.phosphor.line(0, last, pt);
             }
-            lastpt = Some((x, y));
+            lastpt = Some(pt);
         }
+        let map = if self.heat { ColorMap::Heat } else { ColorMap::Tint(Color::RGB(0, 255, 255)) };
+        self.phosphor.draw(&mut self.view, &[map]);
+
+        if let Some(meter) = self.meter.as_mut() {
+            meter.update(left, right);
+        }
+        let names = (info.channels[self.chans.0].name, info.channels[self.chans.1].name);
+        self.render_labels(names, width, height);
+        self.render_meter(names, width, height);
 
         drop(_g);
 
         self.view.present();
     }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+
+    fn key(&mut self, key: Keycode) {
+        match key {
+            Keycode::M => {
+                self.mode = self.mode.next();
+                self.phosphor.clear();
+            },
+            Keycode::H => self.heat = !self.heat,
+            Keycode::A => self.auto_gain = !self.auto_gain,
+            Keycode::D => self.compensate = !self.compensate,
+            _ => (),
+        }
+    }
 }
@@ -0,0 +1,205 @@
+use super::{Info, View};
+use super::pane::Pane;
+
+use std::f32::consts::PI;
+
+use rustfft::num_complex::Complex;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::render::BlendMode;
+
+const MIN_FREQ: f32 = 20.0;
+
+/// Dual-channel (reference/measurement) transfer function H = Sxy/Sxx and
+/// coherence, from exponentially averaged auto- and cross-spectra.
+pub struct Transfer {
+    pub view: Pane,
+    pub reference: usize,
+    pub measure: usize,
+    pub averages: f32,
+    pub db_range: f32,
+    pub compensate: bool,
+    pub sxx: Vec<f32>,
+    pub syy: Vec<f32>,
+    pub sxy: Vec<Complex<f32>>,
+}
+
+impl Transfer {
+    fn accumulate(&mut self, x: &[Complex<f32>], y: &[Complex<f32>], lag: Option<f32>) {
+        let bins = x.len() / 2;
+        if self.sxx.len() != bins {
+            self.reset();
+            self.sxx.resize(bins, 0.0);
+            self.syy.resize(bins, 0.0);
+            self.sxy.resize(bins, Complex { re: 0.0, im: 0.0 });
+        }
+        let alpha = 1.0 / self.averages.max(1.0);
+        // A known delay is removed as a linear phase ramp on the measurement
+        let ramp = lag.map(|d| 2.0 * PI * d / x.len() as f32);
+        for (bin, (&xv, &yv)) in x.iter().zip(y).take(bins).enumerate() {
+            let yv = match ramp {
+                Some(r) => yv * Complex::from_polar(1.0, r * bin as f32),
+                None => yv,
+            };
+            self.sxx[bin] += alpha * (xv.norm_sqr() - self.sxx[bin]);
+            self.syy[bin] += alpha * (yv.norm_sqr() - self.syy[bin]);
+            let dxy = xv.conj() * yv - self.sxy[bin];
+            self.sxy[bin] += dxy * alpha;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sxx.clear();
+        self.syy.clear();
+        self.sxy.clear();
+    }
+
+    fn draw_trace(&mut self, pts: &[Option<i32>], y0: i32, color: Color) {
+        self.view.set_draw_color(color);
+        let mut last: Option<(i32, i32)> = None;
+        for (x, pt) in pts.iter().enumerate() {
+            let pt = pt.map(|y| (x as i32, y0 + y));
+            if let (Some(l), Some(p)) = (last, pt) {
+                self.view.draw_line(l, p).expect("drawing");
+            }
+            last = pt;
+        }
+    }
+}
+
+impl View for Transfer {
+    fn render<'i, 's, 'j: 'i + 's>(&mut self, info: &'j Info<'i, 's>) {
+        let _g = hprof::enter("Transfer::render");
+        let (width, height) = self.view.output_size().expect("getting size");
+        self.view.set_draw_color(Color::RGB(0, 0, 0));
+        self.view.clear();
+
+        let lag = if self.compensate {
+            info.delay.and_then(|e| e.lag_between(self.reference, self.measure))
+        } else {
+            None
+        };
+        self.accumulate(info.channels[self.reference].spectrum, info.channels[self.measure].spectrum, lag);
+        let bins = self.sxx.len();
+        let nyquist = info.rate / 2.0;
+        let bin_of = |x: u32| {
+            let f = MIN_FREQ * (nyquist / MIN_FREQ).powf(x as f32 / width as f32);
+            std::cmp::min((f / nyquist * bins as f32) as usize, bins - 1)
+        };
+
+        let mag_h = height * 2 / 5;
+        let phase_h = height * 2 / 5;
+        let coh_h = height - mag_h - phase_h;
+
+        // Band-average the spectra under each pixel, so the high end is
+        // smoothed rather than aliased
+        let mut mags: Vec<Option<i32>> = Vec::with_capacity(width as usize);
+        let mut phases: Vec<f32> = Vec::with_capacity(width as usize);
+        let mut cohs: Vec<Option<i32>> = Vec::with_capacity(width as usize);
+        let mut coh_vals: Vec<f32> = Vec::with_capacity(width as usize);
+        let mut unwrap = 0f32;
+        let mut last_phase: Option<f32> = None;
+        for x in 0 .. width {
+            let (b0, b1) = (bin_of(x), std::cmp::max(bin_of(x + 1), bin_of(x) + 1));
+            let b1 = std::cmp::min(b1, bins);
+            let sxx: f32 = self.sxx[b0 .. b1].iter().sum();
+            let syy: f32 = self.syy[b0 .. b1].iter().sum();
+            let sxy: Complex<f32> = self.sxy[b0 .. b1].iter().sum();
+            if sxx <= f32::EPSILON {
+                mags.push(None);
+                phases.push(f32::NAN);
+                cohs.push(None);
+                coh_vals.push(0.0);
+                continue;
+            }
+            let h = sxy / sxx;
+            let db = 20.0 * h.norm().max(1e-10).log10();
+            let y = (mag_h as f32 / 2.0 - db / self.db_range * mag_h as f32).clamp(0.0, mag_h as f32 - 1.0);
+            mags.push(Some(y as i32));
+
+            let mut phase = h.arg();
+            if let Some(lp) = last_phase {
+                let delta = phase + unwrap - lp;
+                unwrap -= 2.0 * PI * (delta / (2.0 * PI)).round();
+            }
+            phase += unwrap;
+            last_phase = Some(phase);
+            phases.push(phase);
+
+            let coh = if syy <= f32::EPSILON { 0.0 } else { (sxy.norm_sqr() / (sxx * syy)).min(1.0) };
+            coh_vals.push(coh);
+            cohs.push(Some(((1.0 - coh) * (coh_h as f32 - 1.0)) as i32));
+        }
+
+        // Fit the unwrapped phase span, in whole turns
+        let (pmin, pmax) = phases.iter().filter(|p| p.is_finite())
+            .fold((-PI, PI), |(lo, hi), &p| (lo.min(p), hi.max(p)));
+        let (pmin, pmax) = ((pmin / PI).floor() * PI, (pmax / PI).ceil() * PI);
+        let phase_pts: Vec<Option<i32>> = phases.iter()
+            .map(|&p| if p.is_finite() {
+                Some(((pmax - p) / (pmax - pmin) * (phase_h as f32 - 1.0)) as i32)
+            } else {
+                None
+            })
+            .collect();
+
+        // Grid: 0dB and every 6dB, every half turn of phase, coherence 0.5
+        self.view.set_draw_color(Color::RGB(32, 32, 32));
+        let mut db = -(self.db_range / 12.0).floor() * 6.0;
+        while db <= self.db_range / 2.0 {
+            let y = (mag_h as f32 / 2.0 - db / self.db_range * mag_h as f32) as i32;
+            self.view.draw_line((0, y), (width as i32, y)).expect("drawing");
+            db += 6.0;
+        }
+        let mut ph = pmin;
+        while ph <= pmax {
+            let y = mag_h as i32 + ((pmax - ph) / (pmax - pmin) * (phase_h as f32 - 1.0)) as i32;
+            self.view.draw_line((0, y), (width as i32, y)).expect("drawing");
+            ph += PI;
+        }
+        let y = (mag_h + phase_h + coh_h / 2) as i32;
+        self.view.draw_line((0, y), (width as i32, y)).expect("drawing");
+        self.view.set_draw_color(Color::RGB(63, 63, 63));
+        for &y in &[mag_h, mag_h + phase_h] {
+            self.view.draw_line((0, y as i32), (width as i32, y as i32)).expect("drawing");
+        }
+        let mut decade = 10f32.powf(MIN_FREQ.log10().ceil());
+        while decade < nyquist {
+            let x = ((decade / MIN_FREQ).ln() / (nyquist / MIN_FREQ).ln() * width as f32) as i32;
+            self.view.draw_line((x, 0), (x, height as i32)).expect("drawing");
+            decade *= 10.0;
+        }
+
+        // Magnitude, dimmed where coherence is poor
+        self.view.set_blend_mode(BlendMode::Add);
+        for (x, (m, &coh)) in mags.iter().zip(&coh_vals).enumerate().skip(1) {
+            if let (Some(y0), Some(y1)) = (mags[x - 1], m) {
+                let lvl = (63.0 + 192.0 * coh) as u8;
+                self.view.set_draw_color(Color::RGB(lvl, lvl, 0));
+                self.view.draw_line((x as i32 - 1, y0), (x as i32, *y1)).expect("drawing");
+            }
+        }
+        self.draw_trace(&phase_pts, mag_h as i32, Color::RGB(0, 191, 255));
+        self.draw_trace(&cohs, (mag_h + phase_h) as i32, Color::RGB(255, 0, 0));
+
+        drop(_g);
+
+        self.view.set_blend_mode(BlendMode::None);
+        self.view.present();
+    }
+
+    fn pane(&self) -> &Pane { &self.view }
+
+    fn pane_mut(&mut self) -> &mut Pane { &mut self.view }
+
+    fn key(&mut self, key: Keycode) {
+        match key {
+            Keycode::R => self.reset(),
+            Keycode::D => {
+                self.compensate = !self.compensate;
+                self.reset();
+            },
+            _ => (),
+        }
+    }
+}
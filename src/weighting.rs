@@ -0,0 +1,213 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Standard frequency weighting curves (IEC 61672, ITU-R BS.468).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    A,
+    C,
+    Z,
+    Itu468,
+}
+
+impl Curve {
+    pub fn next(self) -> Curve {
+        match self {
+            Curve::A => Curve::C,
+            Curve::C => Curve::Z,
+            Curve::Z => Curve::Itu468,
+            Curve::Itu468 => Curve::A,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Curve::A => "A",
+            Curve::C => "C",
+            Curve::Z => "Z",
+            Curve::Itu468 => "468",
+        }
+    }
+
+    /// Gain at `freq` Hz, in dB.
+    pub fn gain(self, freq: f32) -> f32 {
+        let f2 = (freq as f64).powi(2);
+        let db = match self {
+            Curve::A => {
+                let r = 12194f64.powi(2) * f2 * f2
+                    / ((f2 + 20.6f64.powi(2))
+                       * ((f2 + 107.7f64.powi(2)) * (f2 + 737.9f64.powi(2))).sqrt()
+                       * (f2 + 12194f64.powi(2)));
+                20.0 * r.log10() + 2.0
+            },
+            Curve::C => {
+                let r = 12194f64.powi(2) * f2
+                    / ((f2 + 20.6f64.powi(2)) * (f2 + 12194f64.powi(2)));
+                20.0 * r.log10() + 0.062
+            },
+            Curve::Z => 0.0,
+            Curve::Itu468 => {
+                let f = freq as f64;
+                let h1 = -4.737338981378384e-24 * f.powi(6) + 2.043828333606125e-15 * f.powi(4)
+                    - 1.363894795463638e-7 * f2 + 1.0;
+                let h2 = 1.306612257412824e-19 * f.powi(5) - 2.118150887518656e-11 * f.powi(3)
+                    + 5.559488023498642e-4 * f;
+                let r = 1.246332637532143e-4 * f / (h1 * h1 + h2 * h2).sqrt();
+                20.0 * r.log10() + 18.2
+            },
+        };
+        db.max(-200.0) as f32
+    }
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Curve, String> {
+        match s {
+            "a" | "A" => Ok(Curve::A),
+            "c" | "C" => Ok(Curve::C),
+            "z" | "Z" => Ok(Curve::Z),
+            "468" => Ok(Curve::Itu468),
+            _ => Err(format!("unknown weighting {} (expected a, c, z or 468)", s)),
+        }
+    }
+}
+
+/// A measured response (e.g. of a microphone) to be removed from spectra,
+/// read from the common text format of one "frequency dB" pair per line.
+/// Anything that doesn't parse as such (headers, comments) is skipped.
+#[derive(Debug, Clone)]
+pub struct Correction {
+    points: Vec<(f32, f32)>,
+}
+
+impl Correction {
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Correction> {
+        Correction::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> std::io::Result<Correction> {
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+        let mut points: Vec<(f32, f32)> = Vec::new();
+        for (num, line) in text.lines().enumerate() {
+            match Correction::pair(line) {
+                Some((freq, db)) if !freq.is_finite() || !db.is_finite() => {
+                    return Err(invalid(format!("line {}: not a finite frequency/dB pair", num + 1)));
+                },
+                Some((freq, db)) if freq > 0.0 => points.push((freq, db)),
+                _ => (),
+            }
+        }
+        if points.is_empty() {
+            return Err(invalid("no frequency/dB pairs".into()));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Correction { points })
+    }
+
+    /// The frequency and dB a line starts with, if it does.
+    fn pair(line: &str) -> Option<(f32, f32)> {
+        let mut fields = line.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .filter(|f| !f.is_empty());
+        Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+    }
+
+    /// The response at `freq`, interpolated on a log-frequency axis and held
+    /// flat beyond the ends.
+    pub fn response(&self, freq: f32) -> f32 {
+        let idx = self.points.partition_point(|&(f, _)| f < freq);
+        if idx == 0 {
+            return self.points[0].1;
+        }
+        if idx == self.points.len() {
+            return self.points[idx - 1].1;
+        }
+        let ((f0, d0), (f1, d1)) = (self.points[idx - 1], self.points[idx]);
+        let t = (freq / f0).ln() / (f1 / f0).ln();
+        d0 + t * (d1 - d0)
+    }
+}
+
+/// A standard curve, plus any correction, as chosen per view.
+#[derive(Debug, Clone)]
+pub struct Weighting {
+    pub curve: Curve,
+    pub correction: Option<Arc<Correction>>,
+}
+
+impl Weighting {
+    pub fn gain(&self, freq: f32) -> f32 {
+        self.curve.gain(freq) - self.correction.as_ref().map_or(0.0, |c| c.response(freq))
+    }
+
+    /// Gain in dB of each bin of the positive half of an FFT of `bins` bins.
+    pub fn table(&self, bins: usize, rate: f32) -> Vec<f32> {
+        let df = rate / bins as f32;
+        (0 .. bins / 2).map(|k| self.gain(k as f32 * df)).collect()
+    }
+
+    pub fn name(&self) -> String {
+        match self.correction {
+            Some(_) => format!("{}-weighted, corrected", self.curve.name()),
+            None => format!("{}-weighted", self.curve.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near(a: f32, b: f32, tol: f32) -> bool {
+        (a - b).abs() < tol
+    }
+
+    #[test]
+    fn curves_match_their_standards() {
+        // IEC 61672-1 table 3 and ITU-R BS.468-4 table 1
+        for (curve, freq, db) in [
+            (Curve::A, 1000.0, 0.0), (Curve::A, 100.0, -19.1), (Curve::A, 10000.0, -2.5),
+            (Curve::C, 1000.0, 0.0), (Curve::C, 31.5, -3.0), (Curve::C, 10000.0, -4.4),
+            (Curve::Itu468, 1000.0, 0.0), (Curve::Itu468, 6300.0, 12.2), (Curve::Itu468, 100.0, -19.8),
+            (Curve::Z, 50.0, 0.0),
+        ] {
+            let gain = curve.gain(freq);
+            assert!(near(gain, db, 0.2), "{} at {} Hz: {}", curve.name(), freq, gain);
+        }
+    }
+
+    #[test]
+    fn curves_parse_and_cycle() {
+        let mut curve = Curve::A;
+        for _ in 0 .. 4 {
+            assert_eq!(curve.name().parse::<Curve>(), Ok(curve));
+            curve = curve.next();
+        }
+        assert_eq!(curve, Curve::A);
+        assert!("b".parse::<Curve>().is_err());
+    }
+
+    #[test]
+    fn correction_interpolates_on_log_frequency() {
+        let c = Correction::parse("Sens Factor =-1.5dB\n\"Hz\" \"dB\"\n100 -2\n1000,0\n10000;4\n").unwrap();
+        assert!(near(c.response(100.0), -2.0, 1e-4));
+        assert!(near(c.response(316.23), -1.0, 1e-3));
+        assert!(near(c.response(3162.3), 2.0, 1e-3));
+        // Held flat beyond the ends
+        assert!(near(c.response(20.0), -2.0, 1e-4));
+        assert!(near(c.response(20000.0), 4.0, 1e-4));
+
+        let w = Weighting { curve: Curve::Z, correction: Some(Arc::new(c)) };
+        assert!(near(w.gain(10000.0), -4.0, 1e-4));
+    }
+
+    #[test]
+    fn correction_rejects_bad_files() {
+        assert!(Correction::parse("no numbers here\n").is_err());
+        assert!(Correction::parse("100 0\n200 nan\n").is_err());
+        assert!(Correction::parse("inf 0\n").is_err());
+    }
+}
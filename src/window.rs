@@ -12,6 +12,43 @@ pub trait Window {
             *datum *= factor;
         }
     }
+
+    /// Mean of the window (the gain it applies to a bin-centered tone).
+    fn coherent_gain(&self) -> f32 {
+        let shp = self.shape();
+        shp.iter().sum::<f32>() / shp.len() as f32
+    }
+
+    /// Equivalent noise bandwidth, in bins.
+    fn enbw(&self) -> f32 {
+        let shp = self.shape();
+        let sum: f32 = shp.iter().sum();
+        let sq: f32 = shp.iter().map(|w| w * w).sum();
+        shp.len() as f32 * sq / (sum * sum)
+    }
+
+    /// Factor turning the squared magnitude of a bin of a spectrum windowed
+    /// by this (and scaled by 1/sqrt(N), N being the length of the data
+    /// before any padding, as in `main`) into one-sided power.
+    fn power_scale(&self) -> f32 {
+        2.0 / (self.size() as f32 * self.coherent_gain().powi(2) * self.enbw())
+    }
+
+    /// Derivative of the window with respect to time (in samples), by
+    /// central differences, for frequency reassignment.
+    fn derivative(&self) -> Vec<f32> {
+        let shp = self.shape();
+        let at = |i: isize| if i < 0 || i as usize >= shp.len() { 0.0 } else { shp[i as usize] };
+        (0 .. shp.len() as isize).map(|i| (at(i + 1) - at(i - 1)) / 2.0).collect()
+    }
+
+    /// The window multiplied by time (in samples) from its center, for time
+    /// reassignment.
+    fn time_weighted(&self) -> Vec<f32> {
+        let shp = self.shape();
+        let center = (shp.len() as f32 - 1.0) / 2.0;
+        shp.iter().enumerate().map(|(i, w)| (i as f32 - center) * w).collect()
+    }
 }
 
 pub struct GenericWindow(Vec<f32>);
@@ -21,6 +58,35 @@ impl Window for GenericWindow {
     fn shape(&self) -> &[f32] { &self.0 }
 }
 
+/// A window followed by zeros, for an FFT longer than the data.
+pub struct Padded {
+    inner: Box<dyn Window>,
+    shape: Vec<f32>,
+}
+
+impl Window for Padded {
+    fn size(&self) -> usize { self.shape.len() }
+    fn shape(&self) -> &[f32] { &self.shape }
+
+    // Spectra are still scaled by the unpadded length
+    fn power_scale(&self) -> f32 {
+        self.inner.power_scale() * self.inner.size() as f32 / self.size() as f32
+    }
+
+    // Time is from the center of the data, not of the padding
+    fn time_weighted(&self) -> Vec<f32> {
+        let mut tw = self.inner.time_weighted();
+        tw.resize(self.size(), 0.0);
+        tw
+    }
+}
+
+pub fn pad(inner: Box<dyn Window>, size: usize) -> Box<dyn Window> {
+    let mut shape = inner.shape().to_vec();
+    shape.resize(size, 0.0);
+    Box::new(Padded { inner, shape })
+}
+
 fn new_rect(sz: usize) -> Box<dyn Window> {
     Box::new(GenericWindow(
             iter::repeat(1.0f32).take(sz).collect()